@@ -0,0 +1,46 @@
+use std::time::Instant;
+
+use shakmaty::CastlingMode;
+
+use crate::engine::Engine;
+use crate::search::{SearchInfo, SearchLimits};
+
+// Openings, middlegames and endgames searched by the bench command
+const POSITIONS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP2BPPP/R2QK2R w KQ - 0 8",
+    "2r3k1/pp3ppp/4p3/3pP3/3P1P2/P1R3P1/1P4KP/8 w - - 0 30",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "6k1/5pp1/7p/8/3B4/8/5PPP/6K1 w - - 0 40",
+    "8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1",
+];
+
+pub const DEFAULT_DEPTH: i16 = 6;
+
+// Searches every bench position to a fixed depth with the engine's options and
+// returns the total node count, a signature for changes that should not alter the search
+pub fn run(engine: &Engine, depth: i16) -> u64 {
+    let mut engine = engine.clone();
+    let limits = SearchLimits { depth: Some(depth), ..Default::default() };
+    let start = Instant::now();
+    let mut total = 0;
+    for fen in POSITIONS {
+        engine.set_position(Some(fen), &[]).unwrap();
+        let mut nodes = 0;
+        let result = engine.search(&limits, |info: &SearchInfo| {
+            if let SearchInfo::Iteration { nodes: n, .. } = info {
+                nodes = *n;
+            }
+        });
+        let best_move = result.best_move.map_or("0000".to_string(), |m| m.to_uci(CastlingMode::Standard).to_string());
+        println!("{} bestmove {} nodes {}", fen, best_move, nodes);
+        total += nodes;
+    }
+    let elapsed = start.elapsed();
+    println!("Nodes searched: {}", total);
+    println!("Time (ms): {}", elapsed.as_millis());
+    println!("Nodes/second: {}", (total as f64 / elapsed.as_secs_f64().max(0.001)) as u64);
+    total
+}
@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use shakmaty::{Bitboard, Board, Chess, Color, File, Position, Rank, Role, Square};
+
+use crate::evaluation::PIECE_VALUES;
+use crate::kpk;
+use crate::score::Score;
+use crate::utils::get_color_factor;
+
+// Scale factors are applied to the endgame part of the evaluation, out of SCALE_FACTOR_NORMAL
+pub const SCALE_FACTOR_NORMAL: i32 = 64;
+const SCALE_FACTOR_DRAW: i32 = 0;
+
+// A won endgame scores at least this much, so it is preferred over any normal evaluation
+const KNOWN_WIN: i16 = 1000;
+
+const PAWN_VALUE: i16 = PIECE_VALUES[Role::Pawn as usize];
+const KNIGHT_VALUE: i16 = PIECE_VALUES[Role::Knight as usize];
+const BISHOP_VALUE: i16 = PIECE_VALUES[Role::Bishop as usize];
+const ROOK_VALUE: i16 = PIECE_VALUES[Role::Rook as usize];
+const QUEEN_VALUE: i16 = PIECE_VALUES[Role::Queen as usize];
+
+// Evaluates the position for the strong side
+type EndgameFn = fn(&Chess, Color) -> i16;
+
+// Material signatures with a specialized evaluator, written with the strong side first
+const ENDGAMES: [(&str, EndgameFn); 5] = [
+    ("KBNvK", evaluate_kbnk),
+    ("KNNvK", evaluate_knnk),
+    ("KPvK", evaluate_kpk),
+    ("KRvKP", evaluate_krkp),
+    ("KQvKP", evaluate_kqkp),
+];
+
+// Piece counts of both sides packed four bits per role
+pub fn material_key(board: &Board) -> u64 {
+    let material = board.material();
+    let mut key = 0;
+    for (shift, color) in [(0, Color::White), (32, Color::Black)] {
+        let side = material.get(color);
+        for role in [Role::Pawn, Role::Knight, Role::Bishop, Role::Rook, Role::Queen] {
+            key |= (*side.get(role) as u64).min(15) << (shift + 4 * (role as u64 - 1));
+        }
+    }
+    key
+}
+
+// Material key of a signature such as "KBNvK", with the first side playing the given color
+fn signature_key(signature: &str, strong: Color) -> u64 {
+    let mut board = Board::empty();
+    let (first, second) = signature.split_once('v').unwrap();
+    for (side, color) in [(first, strong), (second, !strong)] {
+        for (i, ch) in side.chars().enumerate() {
+            let role = Role::from_char(ch.to_ascii_lowercase()).unwrap();
+            let rank = if color == Color::White { Rank::First } else { Rank::Eighth };
+            board.set_piece_at(Square::from_coords(File::new(i as u32), rank), role.of(color));
+        }
+    }
+    material_key(&board)
+}
+
+fn endgames() -> &'static HashMap<u64, (EndgameFn, Color)> {
+    static ENDGAME_TABLE: OnceLock<HashMap<u64, (EndgameFn, Color)>> = OnceLock::new();
+    ENDGAME_TABLE.get_or_init(|| {
+        let mut table = HashMap::new();
+        for (signature, eval) in ENDGAMES {
+            for strong in [Color::White, Color::Black] {
+                table.insert(signature_key(signature, strong), (eval, strong));
+            }
+        }
+        table
+    })
+}
+
+fn non_pawn_material(board: &Board, color: Color) -> i16 {
+    let side = board.material_side(color);
+    side.knight as i16 * KNIGHT_VALUE
+        + side.bishop as i16 * BISHOP_VALUE
+        + side.rook as i16 * ROOK_VALUE
+        + side.queen as i16 * QUEEN_VALUE
+}
+
+// Enough to force mate against a bare king: a queen or rook, bishop and knight,
+// or bishops on both colors
+fn has_mating_material(board: &Board, color: Color) -> bool {
+    let side = board.material_side(color);
+    let bishops = board.bishops() & board.by_color(color);
+    side.queen > 0
+        || side.rook > 0
+        || (side.bishop > 0 && side.knight > 0)
+        || ((bishops & Bitboard::LIGHT_SQUARES).any() && (bishops & Bitboard::DARK_SQUARES).any())
+}
+
+// Specialized evaluation from white's point of view, if the material has one
+pub fn evaluate_endgame(pos: &Chess) -> Option<Score> {
+    let board = pos.board();
+    let (eval, strong) = match endgames().get(&material_key(board)) {
+        Some(&entry) => entry,
+        None => {
+            // KXK: mating material against a bare king
+            let strong = [Color::White, Color::Black]
+                .into_iter()
+                .find(|&c| board.by_color(!c).count() == 1 && has_mating_material(board, c))?;
+            (evaluate_kxk as EndgameFn, strong)
+        }
+    };
+    Some(Score::Centipawn(eval(pos, strong) * get_color_factor(strong)))
+}
+
+// Scale factor for the endgame evaluation of the given strong side
+pub fn scale_factor(board: &Board, strong: Color) -> i32 {
+    let weak = !strong;
+    let strong_pawns = board.pawns() & board.by_color(strong);
+    let strong_npm = non_pawn_material(board, strong);
+    let weak_npm = non_pawn_material(board, weak);
+
+    // Without pawns, an advantage of a minor piece or less is usually not enough
+    if strong_pawns.is_empty() && strong_npm - weak_npm <= BISHOP_VALUE {
+        return if strong_npm < ROOK_VALUE {
+            SCALE_FACTOR_DRAW
+        } else if weak_npm <= BISHOP_VALUE {
+            4
+        } else {
+            14
+        };
+    }
+
+    // Bishop and rook pawns of the wrong color against a king in the corner
+    if strong_npm == BISHOP_VALUE && strong_pawns.any() && is_wrong_rook_pawn_fortress(board, strong) {
+        return SCALE_FACTOR_DRAW;
+    }
+
+    // Opposite-colored bishops
+    let strong_bishops = board.bishops() & board.by_color(strong);
+    let weak_bishops = board.bishops() & board.by_color(weak);
+    if let (Some(a), Some(b)) = (strong_bishops.single_square(), weak_bishops.single_square())
+        && a.is_light() != b.is_light()
+    {
+        if strong_npm == BISHOP_VALUE && weak_npm == BISHOP_VALUE {
+            let weak_pawns = (board.pawns() & board.by_color(weak)).count();
+            return if strong_pawns.count() <= weak_pawns + 1 { 16 } else { 32 };
+        }
+        return 48;
+    }
+
+    SCALE_FACTOR_NORMAL
+}
+
+fn is_wrong_rook_pawn_fortress(board: &Board, strong: Color) -> bool {
+    let pawns = board.pawns() & board.by_color(strong);
+    let file = match pawns.first().map(|sq| sq.file()) {
+        Some(file @ (File::A | File::H)) if pawns.is_subset(Bitboard::from_file(file)) => file,
+        _ => return false,
+    };
+    let promotion = Square::from_coords(file, strong.relative_rank(Rank::Eighth));
+    let bishop = (board.bishops() & board.by_color(strong)).first().unwrap();
+    let weak_king = board.king_of(!strong).unwrap();
+    bishop.is_light() != promotion.is_light() && weak_king.distance(promotion) <= 1
+}
+
+fn push_to_edge(square: Square) -> i16 {
+    let file = square.file() as i16;
+    let rank = square.rank() as i16;
+    let fd = file.min(7 - file);
+    let rd = rank.min(7 - rank);
+    90 - (7 * fd * fd / 2 + 7 * rd * rd / 2)
+}
+
+// Highest on the a1 and h8 corners, zero on the a8-h1 diagonal
+fn push_to_corner(square: Square) -> i16 {
+    (7 - square.rank() as i16 - square.file() as i16).abs()
+}
+
+fn push_close(a: Square, b: Square) -> i16 {
+    140 - 20 * a.distance(b) as i16
+}
+
+fn material(board: &Board, color: Color) -> i16 {
+    non_pawn_material(board, color) + (board.pawns() & board.by_color(color)).count() as i16 * PAWN_VALUE
+}
+
+// Mate with enough material: drive the weak king to the edge and bring the kings together
+fn evaluate_kxk(pos: &Chess, strong: Color) -> i16 {
+    let board = pos.board();
+    let strong_king = board.king_of(strong).unwrap();
+    let weak_king = board.king_of(!strong).unwrap();
+    let score = material(board, strong) + push_to_edge(weak_king) + push_close(strong_king, weak_king);
+    (score + KNOWN_WIN).min(KNOWN_WIN * 4)
+}
+
+// Bishop and knight mate: the weak king has to go to a corner of the bishop's color
+fn evaluate_kbnk(pos: &Chess, strong: Color) -> i16 {
+    let board = pos.board();
+    let strong_king = board.king_of(strong).unwrap();
+    let weak_king = board.king_of(!strong).unwrap();
+    let bishop = (board.bishops() & board.by_color(strong)).first().unwrap();
+    let corner_king = if bishop.is_dark() == Square::A1.is_dark() { weak_king } else { weak_king.flip_horizontal() };
+    KNOWN_WIN + push_close(strong_king, weak_king) + 20 * push_to_corner(corner_king)
+}
+
+// Two knights cannot force mate
+fn evaluate_knnk(_pos: &Chess, _strong: Color) -> i16 {
+    0
+}
+
+// King and pawn against king, exact from the bitbase
+fn evaluate_kpk(pos: &Chess, strong: Color) -> i16 {
+    let board = pos.board();
+    let pawn = (board.pawns() & board.by_color(strong)).first().unwrap();
+    let strong_king = board.king_of(strong).unwrap();
+    let weak_king = board.king_of(!strong).unwrap();
+    let pawn_rank = strong.relative_rank(pawn.rank()) as i16;
+
+    if kpk::probe(strong, strong_king, pawn, weak_king, pos.turn()) {
+        KNOWN_WIN + PAWN_VALUE + pawn_rank * 10
+    } else {
+        0
+    }
+}
+
+// Rook against pawn: usually a win unless the pawn is far advanced and supported
+fn evaluate_krkp(pos: &Chess, strong: Color) -> i16 {
+    let board = pos.board();
+    let weak = !strong;
+    let strong_king = board.king_of(strong).unwrap();
+    let weak_king = board.king_of(weak).unwrap();
+    let rook = (board.rooks() & board.by_color(strong)).first().unwrap();
+    let pawn = (board.pawns() & board.by_color(weak)).first().unwrap();
+    let queening = Square::from_coords(pawn.file(), weak.relative_rank(Rank::Eighth));
+    let tempo = if pos.turn() == weak { 1 } else { 0 };
+    let strong_king_in_front = strong_king.file() == pawn.file()
+        && weak.relative_rank(strong_king.rank()) > weak.relative_rank(pawn.rank());
+
+    if strong_king_in_front {
+        return ROOK_VALUE - strong_king.distance(pawn) as i16;
+    }
+    if weak_king.distance(pawn) as i16 >= 3 + tempo && weak_king.distance(rook) >= 3 {
+        return ROOK_VALUE - strong_king.distance(pawn) as i16;
+    }
+    let weak_pawn_rank = weak.relative_rank(pawn.rank()) as i16;
+    if weak.relative_rank(weak_king.rank()) as i16 >= 5
+        && weak_king.distance(pawn) == 1
+        && strong.relative_rank(strong_king.rank()) as i16 >= 4
+        && strong_king.distance(pawn) as i16 > 2 + 1 - tempo
+    {
+        return 80 - 8 * strong_king.distance(pawn) as i16;
+    }
+    200 - 8 * (strong_king.distance(queening) as i16 - weak_king.distance(queening) as i16 - weak_pawn_rank)
+}
+
+// Queen against pawn: a win, except for a rook or bishop pawn on the 7th supported by its king
+fn evaluate_kqkp(pos: &Chess, strong: Color) -> i16 {
+    let board = pos.board();
+    let weak = !strong;
+    let strong_king = board.king_of(strong).unwrap();
+    let weak_king = board.king_of(weak).unwrap();
+    let pawn = (board.pawns() & board.by_color(weak)).first().unwrap();
+    let mut result = push_close(strong_king, weak_king);
+    let drawish = weak.relative_rank(pawn.rank()) == Rank::Seventh
+        && weak_king.distance(pawn) == 1
+        && matches!(pawn.file(), File::A | File::C | File::F | File::H);
+    if !drawish {
+        result += QUEEN_VALUE - PAWN_VALUE;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::position;
+
+    fn eval(fen: &str) -> i16 {
+        match evaluate_endgame(&position(fen)) {
+            Some(Score::Centipawn(value)) => value,
+            other => panic!("unexpected endgame score {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_material_key() {
+        let pos = position("4k3/8/8/8/8/8/8/1N2KB2 w - - 0 1");
+        assert_eq!(material_key(pos.board()), signature_key("KBNvK", Color::White));
+        assert_ne!(material_key(pos.board()), signature_key("KBNvK", Color::Black));
+    }
+
+    #[test]
+    fn test_kxk_drives_king_to_edge() {
+        let edge = eval("7k/8/5K2/8/8/8/8/R7 w - - 0 1");
+        let center = eval("8/8/8/4k3/8/2K5/8/R7 w - - 0 1");
+        assert!(edge > center);
+        assert!(center >= KNOWN_WIN);
+        assert!(eval("r7/8/8/4K3/8/2k5/8/8 w - - 0 1") <= -KNOWN_WIN);
+    }
+
+    #[test]
+    fn test_kxk_needs_mating_material() {
+        assert_eq!(eval("8/8/8/4k3/8/2K5/8/1N4N1 w - - 0 1"), 0);
+        assert_eq!(eval("1n4n1/8/8/4K3/8/2k5/8/8 b - - 0 1"), 0);
+        // Same-colored bishops cannot mate, bishops on both colors can
+        assert!(evaluate_endgame(&position("8/8/8/4k3/8/2K5/8/2B1B3 w - - 0 1")).is_none());
+        assert!(eval("8/8/8/4k3/8/2K5/8/2B2B2 w - - 0 1") >= KNOWN_WIN);
+    }
+
+    #[test]
+    fn test_kbnk_prefers_bishop_corner() {
+        // Dark-squared bishop mates on a1 or h8
+        let right_corner = eval("7k/8/6K1/8/8/8/8/2B1N3 w - - 0 1");
+        let wrong_corner = eval("k7/8/1K6/8/8/8/8/2B1N3 w - - 0 1");
+        assert!(right_corner > wrong_corner);
+    }
+
+    #[test]
+    fn test_kpk() {
+        // Unstoppable pawn
+        assert!(eval("8/8/8/P7/8/8/8/K6k w - - 0 1") >= KNOWN_WIN);
+        // Defending king in front of the pawn
+        assert_eq!(eval("4k3/8/8/4P3/4K3/8/8/8 b - - 0 1"), 0);
+        // King on a key square
+        assert!(eval("3k4/8/3K4/4P3/8/8/8/8 w - - 0 1") >= KNOWN_WIN);
+    }
+
+    #[test]
+    fn test_krkp() {
+        let king_in_front = eval("8/8/8/8/5k2/8/1p6/1K1R4 w - - 0 1");
+        let supported_pawn = eval("7K/8/8/8/8/8/2pk4/R7 w - - 0 1");
+        assert!(king_in_front > supported_pawn);
+        assert!(supported_pawn < ROOK_VALUE / 2);
+    }
+
+    #[test]
+    fn test_kqkp() {
+        let winning = eval("8/8/8/8/Q7/1K6/4p3/5k2 w - - 0 1");
+        let rook_pawn = eval("6Q1/8/8/8/8/8/pk6/7K w - - 0 1");
+        assert!(winning > QUEEN_VALUE / 2);
+        assert!(rook_pawn < QUEEN_VALUE / 2);
+    }
+
+    #[test]
+    fn test_scale_factors() {
+        let ocb = position("4k3/3b4/8/3p4/3P4/8/3B4/4K3 w - - 0 1");
+        assert_eq!(scale_factor(ocb.board(), Color::White), 16);
+        let same_color = position("4k3/4b3/8/3p4/3P4/8/3B4/4K3 w - - 0 1");
+        assert_eq!(scale_factor(same_color.board(), Color::White), SCALE_FACTOR_NORMAL);
+        let minor_up = position("4k3/8/8/8/8/8/8/3NK3 w - - 0 1");
+        assert_eq!(scale_factor(minor_up.board(), Color::White), SCALE_FACTOR_DRAW);
+        let rook_vs_bishop = position("4k3/4b3/8/8/8/8/8/3RK3 w - - 0 1");
+        assert_eq!(scale_factor(rook_vs_bishop.board(), Color::White), 4);
+        let wrong_bishop = position("k7/8/8/P7/8/8/8/2B1K3 w - - 0 1");
+        assert_eq!(scale_factor(wrong_bishop.board(), Color::White), SCALE_FACTOR_DRAW);
+        let right_bishop = position("k7/8/8/P7/8/8/8/3BK3 w - - 0 1");
+        assert_eq!(scale_factor(right_bishop.board(), Color::White), SCALE_FACTOR_NORMAL);
+    }
+}
@@ -0,0 +1,359 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError};
+use std::thread;
+
+use shakmaty::fen::Fen;
+use shakmaty::uci::UciMove;
+use shakmaty::variant::{Variant, VariantPosition};
+use shakmaty::{CastlingMode, Position};
+
+use crate::evaluation::{calculate_score, evaluation_breakdown};
+use crate::kpk;
+use crate::pawn_table::PawnTable;
+use crate::score::{Score, S};
+use crate::search::{find_best_move, SearchControl, SearchLimits, SearchObserver, SearchOptions, SearchResult};
+use crate::tablebase::Tablebase;
+use crate::transposition::TranspositionTable;
+
+const HASH_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineError {
+    InvalidFen(String),
+    IllegalMove(String),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::InvalidFen(fen) => write!(f, "invalid position: {}", fen),
+            EngineError::IllegalMove(uci) => write!(f, "illegal move: {}", uci),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+/// The engine behind the UCI and XBoard front ends: a position to search and the
+/// settings that outlive a single search.
+///
+/// ```
+/// use voin::{Engine, SearchLimits};
+///
+/// let mut engine = Engine::new();
+/// engine.set_position(None, &["e2e4", "e7e5"]).unwrap();
+/// let result = engine.search(&SearchLimits { depth: Some(2), ..Default::default() }, ());
+/// assert_eq!(result.pv.first(), result.best_move.as_ref());
+/// ```
+pub struct Engine {
+    pos: VariantPosition,
+    tablebase: Arc<Tablebase>,
+    options: SearchOptions,
+    // Kept between searches, locked by the running search
+    transposition_table: Arc<Mutex<TranspositionTable>>,
+}
+
+// A clone starts with an empty transposition table, so that its searches do not
+// depend on the original's
+impl Clone for Engine {
+    fn clone(&self) -> Self {
+        Self {
+            pos: self.pos.clone(),
+            tablebase: Arc::clone(&self.tablebase),
+            options: self.options.clone(),
+            transposition_table: Arc::new(Mutex::new(TranspositionTable::new(HASH_SIZE))),
+        }
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self {
+            pos: VariantPosition::new(Variant::Chess),
+            tablebase: Arc::new(Tablebase),
+            options: SearchOptions::default(),
+            transposition_table: Arc::new(Mutex::new(TranspositionTable::new(HASH_SIZE))),
+        }
+    }
+
+    /// Builds the endgame bitbases now rather than during the first search.
+    pub fn init_tables(&self) {
+        kpk::init();
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.pos.variant()
+    }
+
+    /// Switches variant and resets to its starting position.
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.pos = VariantPosition::new(variant);
+    }
+
+    pub fn position(&self) -> &VariantPosition {
+        &self.pos
+    }
+
+    /// Replaces the position, keeping its variant.
+    pub fn set_variant_position(&mut self, pos: VariantPosition) {
+        self.pos = pos;
+    }
+
+    /// Sets up a FEN, or the starting position with `None`, then plays the UCI moves.
+    /// The position is left untouched on error.
+    ///
+    /// ```
+    /// use voin::{Engine, EngineError};
+    ///
+    /// let mut engine = Engine::new();
+    /// engine.set_position(Some("4k3/8/8/8/8/8/8/4K2R w K - 0 1"), &["e1g1"]).unwrap();
+    /// assert_eq!(engine.fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+    /// assert_eq!(engine.set_position(None, &["e2e5"]), Err(EngineError::IllegalMove("e2e5".to_string())));
+    /// ```
+    pub fn set_position(&mut self, fen: Option<&str>, moves: &[&str]) -> Result<(), EngineError> {
+        let mut pos = match fen {
+            None => VariantPosition::new(self.variant()),
+            Some(text) => text
+                .parse::<Fen>()
+                .ok()
+                .and_then(|fen| VariantPosition::from_setup(self.variant(), fen.into_setup(), CastlingMode::Standard).ok())
+                .ok_or_else(|| EngineError::InvalidFen(text.to_string()))?,
+        };
+        for text in moves {
+            let m = text
+                .parse::<UciMove>()
+                .ok()
+                .and_then(|uci| uci.to_move(&pos).ok())
+                .ok_or_else(|| EngineError::IllegalMove(text.to_string()))?;
+            pos.play_unchecked(&m);
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    pub fn fen(&self) -> String {
+        Fen::from_position(self.pos.clone(), shakmaty::EnPassantMode::Legal).to_string()
+    }
+
+    /// Search features used from the next search on.
+    ///
+    /// ```
+    /// let mut engine = voin::Engine::new();
+    /// engine.options_mut().iid = true;
+    /// assert!(engine.options().iid);
+    /// ```
+    pub fn options_mut(&mut self) -> &mut SearchOptions {
+        &mut self.options
+    }
+
+    pub fn options(&self) -> &SearchOptions {
+        &self.options
+    }
+
+    /// Forgets what earlier searches learned, as for a new game. Waits for a running
+    /// search to finish, so stop it first.
+    pub fn clear_hash(&mut self) {
+        self.table().clear();
+    }
+
+    /// Writes the transposition table to a file, to be loaded in a later session.
+    ///
+    /// ```
+    /// use voin::{Engine, SearchLimits};
+    ///
+    /// let path = std::env::temp_dir().join("voin-doc.hash");
+    /// let engine = Engine::new();
+    /// engine.search(&SearchLimits { depth: Some(4), ..Default::default() }, ());
+    /// engine.save_hash(&path).unwrap();
+    ///
+    /// let mut restored = Engine::new();
+    /// restored.load_hash(&path).unwrap();
+    /// assert!(restored.load_hash(path.with_extension("missing")).is_err());
+    /// # std::fs::remove_file(path).unwrap();
+    /// ```
+    pub fn save_hash(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.idle_table()?.save(path)
+    }
+
+    /// Replaces the transposition table with one saved by [`Engine::save_hash`]. Files
+    /// from another engine version or table size are rejected, keeping the current table.
+    pub fn load_hash(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.idle_table()?.load(path)
+    }
+
+    // The table is only available between searches
+    fn idle_table(&self) -> io::Result<MutexGuard<'_, TranspositionTable>> {
+        match self.transposition_table.try_lock() {
+            Ok(table) => Ok(table),
+            Err(TryLockError::Poisoned(error)) => Ok(error.into_inner()),
+            Err(TryLockError::WouldBlock) => Err(io::Error::new(io::ErrorKind::WouldBlock, "a search is running")),
+        }
+    }
+
+    // Waits for a running search. A search that panicked leaves the table usable, as
+    // entries are only ever written whole.
+    fn table(&self) -> MutexGuard<'_, TranspositionTable> {
+        self.transposition_table.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Static evaluation of the position from white's point of view.
+    ///
+    /// ```
+    /// use voin::{Engine, Score};
+    ///
+    /// let mut engine = Engine::new();
+    /// let score = engine.evaluate();
+    /// assert!(score > Score::Centipawn(-50) && score < Score::Centipawn(50));
+    /// engine.set_position(Some("4k3/8/8/8/8/8/8/3QK3 w - - 0 1"), &[]).unwrap();
+    /// assert!(engine.evaluate() > Score::Centipawn(500));
+    /// ```
+    pub fn evaluate(&self) -> Score {
+        calculate_score(&self.pos, &mut PawnTable::new(1))
+    }
+
+    /// Per-term middlegame and endgame scores from white's point of view, ending with
+    /// the total, along with the game phase.
+    pub fn evaluation_breakdown(&self) -> (Vec<(&'static str, S)>, i32) {
+        evaluation_breakdown(&self.pos, &mut PawnTable::new(1))
+    }
+
+    /// Searches the position on the calling thread until a limit is reached, passing
+    /// progress to the observer.
+    ///
+    /// ```
+    /// use voin::{Bound, Engine, SearchInfo, SearchLimits};
+    ///
+    /// let engine = Engine::new();
+    /// let mut depths = Vec::new();
+    /// engine.search(&SearchLimits { depth: Some(3), ..Default::default() }, |info: &SearchInfo| {
+    ///     if let SearchInfo::Iteration { depth, bound: Bound::Exact, .. } = info {
+    ///         depths.push(*depth);
+    ///     }
+    /// });
+    /// assert_eq!(depths, [1, 2, 3]);
+    /// ```
+    pub fn search(&self, limits: &SearchLimits, mut observer: impl SearchObserver) -> SearchResult {
+        let control = SearchControl::new(limits.ponder);
+        let mut table = self.table();
+        find_best_move(&self.pos, limits, &mut table, &self.tablebase, &self.options, &control, &mut observer)
+    }
+
+    /// Searches the position on a new thread, which hands the result to `on_done`.
+    ///
+    /// ```
+    /// use std::sync::mpsc;
+    /// use voin::{Engine, SearchLimits};
+    ///
+    /// let engine = Engine::new();
+    /// let (tx, rx) = mpsc::channel();
+    /// let search = engine.start_search(
+    ///     SearchLimits { infinite: true, ..Default::default() },
+    ///     (),
+    ///     move |result| tx.send(result).unwrap(),
+    /// );
+    /// search.stop();
+    /// let result = rx.recv().unwrap();
+    /// assert_eq!(result.pv.first(), result.best_move.as_ref());
+    /// ```
+    pub fn start_search(
+        &self,
+        limits: SearchLimits,
+        mut observer: impl SearchObserver + Send + 'static,
+        on_done: impl FnOnce(SearchResult) + Send + 'static,
+    ) -> SearchHandle {
+        let control = Arc::new(SearchControl::new(limits.ponder));
+        let search_control = Arc::clone(&control);
+        let pos = self.pos.clone();
+        let tablebase = Arc::clone(&self.tablebase);
+        let options = self.options.clone();
+        let table = Arc::clone(&self.transposition_table);
+        thread::spawn(move || {
+            // The table is released before the result is handed over, so it can be saved right away
+            let result = {
+                let mut table = table.lock().unwrap_or_else(PoisonError::into_inner);
+                find_best_move(&pos, &limits, &mut table, &tablebase, &options, &search_control, &mut observer)
+            };
+            on_done(result);
+        });
+        SearchHandle { control }
+    }
+}
+
+/// Controls a search started with [`Engine::start_search`].
+pub struct SearchHandle {
+    control: Arc<SearchControl>,
+}
+
+impl SearchHandle {
+    /// Ends the search as soon as possible; it still reports the best move found.
+    pub fn stop(&self) {
+        self.control.stop();
+    }
+
+    /// Turns a ponder search into a timed one.
+    pub fn ponderhit(&self) {
+        self.control.ponderhit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    use crate::search::SearchInfo;
+
+    #[test]
+    fn test_search_without_legal_moves() {
+        let mut engine = Engine::new();
+        for fen in ["R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"] {
+            engine.set_position(Some(fen), &[]).unwrap();
+            let result = engine.search(&SearchLimits { depth: Some(5), ..Default::default() }, ());
+            assert!(result.best_move.is_none() && result.pv.is_empty());
+
+            let (tx, rx) = mpsc::channel();
+            let search = engine.start_search(SearchLimits { infinite: true, ..Default::default() }, (), move |result| {
+                tx.send(result).unwrap()
+            });
+            search.stop();
+            assert!(rx.recv().unwrap().best_move.is_none());
+        }
+
+        // The table is still usable afterwards
+        engine.set_position(None, &["e2e4"]).unwrap();
+        let result = engine.search(&SearchLimits { depth: Some(3), ..Default::default() }, ());
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn test_ponderhit_keeps_time_limit() {
+        let mut engine = Engine::new();
+        engine.set_position(Some("r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N2N2/PP2BPPP/R2QKB1R w KQ - 0 9"), &[]).unwrap();
+        let budget = Duration::from_millis(50);
+        let (tx, rx) = mpsc::channel();
+        let (iterations, iteration_times) = mpsc::channel();
+        let observer = move |info: &SearchInfo| {
+            if let SearchInfo::Iteration { time, .. } = info {
+                let _ = iterations.send(*time);
+            }
+        };
+        let limits = SearchLimits { time: Some(budget), ponder: true, ..Default::default() };
+        let search = engine.start_search(limits, observer, move |result| tx.send(result).unwrap());
+        // Well past the budget, at the start of an iteration that takes about as long again
+        while iteration_times.recv().unwrap() < budget * 10 {}
+        thread::sleep(budget / 5);
+        let hit = Instant::now();
+        search.ponderhit();
+        assert!(rx.recv().unwrap().best_move.is_some());
+        assert!(hit.elapsed() < budget * 2, "{:?} after ponderhit", hit.elapsed());
+    }
+}
@@ -1,5 +1,5 @@
-use shakmaty::{Chess, Position, Role, Piece, Color, Outcome, Square, File, Board};
-use crate::{score::Score, utils::*};
+use shakmaty::{attacks, variant::Variant, Bitboard, Board, CastlingSide, Color, File, Outcome, Piece, Position, Rank, Role, Square};
+use crate::{endgame::{evaluate_endgame, scale_factor, SCALE_FACTOR_NORMAL}, pawn_table::{pawn_key, PawnEntry, PawnTable}, score::{Score, S, MAX_PHASE}, utils::*, variant::EnginePosition};
 
 // Pawn tables
 const PAWN_MG: [i16; 64] = [
@@ -139,21 +139,100 @@ const KING_EG: [i16; 64] = [
     -50, -30, -30, -30, -30, -30, -30, -50,
 ];
 
-// Piece values for material evaluation
+// Passed pawn bonuses, indexed by relative rank
+const PASSED_PAWN: [S; 8] = [S(0, 0), S(0, 10), S(5, 15), S(10, 25), S(20, 45), S(35, 70), S(60, 110), S(0, 0)];
+const CANDIDATE_PASSER: [S; 8] = [S(0, 0), S(2, 5), S(4, 8), S(7, 12), S(12, 20), S(20, 30), S(0, 0), S(0, 0)];
+const CONNECTED_PASSER: [S; 8] = [S(0, 0), S(0, 5), S(3, 8), S(5, 12), S(10, 20), S(20, 35), S(30, 55), S(0, 0)];
+
+const ISOLATED_PAWN: S = S(15, 15);
+const DOUBLED_PAWN: S = S(20, 20);
+
+// Endgame passed pawn terms are multiplied by this rank weight
+const PASSER_RANK_WEIGHT: [i16; 8] = [0, 0, 0, 1, 2, 4, 6, 0];
+
+const FREE_PATH_EG: i16 = 5;
+const SAFE_PATH_EG: i16 = 10;
+const BLOCKADED_PASSER_EG: i16 = 4;
+const OWN_KING_DISTANCE_EG: i16 = 2;
+const ENEMY_KING_DISTANCE_EG: i16 = 4;
+const ROOK_BEHIND_PASSER_EG: i16 = 25;
+
+// Mobility bonuses indexed by the number of reachable squares in the mobility area
+const KNIGHT_MOBILITY: [S; 9] = [
+    S(-31, -40), S(-26, -28), S(-6, -15), S(-2, -8), S(2, 3), S(6, 6), S(11, 8), S(14, 10), S(16, 12),
+];
+const BISHOP_MOBILITY: [S; 14] = [
+    S(-24, -30), S(-10, -11), S(8, -2), S(13, 6), S(19, 12), S(25, 21), S(27, 27),
+    S(31, 28), S(31, 32), S(34, 36), S(40, 39), S(40, 43), S(45, 44), S(49, 48),
+];
+const ROOK_MOBILITY: [S; 15] = [
+    S(-30, -39), S(-10, -8), S(1, 11), S(2, 19), S(2, 35), S(6, 49), S(11, 51), S(15, 60),
+    S(20, 67), S(20, 69), S(20, 79), S(24, 82), S(28, 84), S(28, 84), S(31, 86),
+];
+const QUEEN_MOBILITY: [S; 28] = [
+    S(-15, -24), S(-6, -15), S(-4, -3), S(-4, 10), S(10, 20), S(11, 27), S(11, 29),
+    S(17, 37), S(19, 39), S(26, 48), S(32, 48), S(32, 50), S(32, 60), S(33, 63),
+    S(33, 65), S(33, 66), S(36, 68), S(36, 70), S(38, 73), S(39, 75), S(46, 75),
+    S(54, 84), S(54, 84), S(54, 85), S(55, 91), S(57, 91), S(57, 96), S(58, 109),
+];
+
+// King safety attack units per attacked king zone square and per safe check, indexed by role
+const KING_ATTACK_WEIGHT: [usize; 7] = [0, 0, 2, 2, 3, 5, 0];
+const SAFE_CHECK_UNITS: [usize; 7] = [0, 0, 3, 2, 4, 6, 0];
+
+// Shield units for a pawn one step ahead, two steps ahead, or missing
+const SHIELD_UNITS: [usize; 3] = [0, 1, 3];
+// Storm units by distance of the enemy pawn to the king rank
+const STORM_UNITS: [usize; 4] = [0, 1, 3, 2];
+const OPEN_FILE_UNITS: usize = 3;
+const SEMI_OPEN_FILE_UNITS: usize = 2;
+
+// Maps king attack units to a nonlinear penalty
+const KING_SAFETY_TABLE: [i16; 100] = [
+      0,   0,   1,   2,   3,   5,   7,   9,  12,  15,
+     18,  22,  26,  30,  35,  39,  44,  50,  56,  62,
+     68,  75,  82,  85,  89,  97, 105, 113, 122, 131,
+    140, 150, 169, 180, 191, 202, 213, 225, 237, 248,
+    260, 272, 283, 295, 307, 319, 330, 342, 354, 366,
+    377, 389, 401, 412, 424, 436, 448, 459, 471, 483,
+    494, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+];
+
+// Piece-specific terms
+const ROOK_OPEN_FILE: S = S(40, 20);
+const ROOK_SEMI_OPEN_FILE: S = S(20, 10);
+const ROOK_ON_SEVENTH: S = S(20, 40);
+const KNIGHT_OUTPOST: S = S(30, 20);
+const BISHOP_OUTPOST: S = S(15, 10);
+// Per own pawn on the bishop's square color
+const BAD_BISHOP_PAWN: S = S(3, 6);
+const TRAPPED_BISHOP: S = S(120, 120);
+const TRAPPED_ROOK: S = S(50, 20);
+// Per minor piece still at home while the queen is out
+const QUEEN_EARLY_DEVELOPMENT: S = S(10, 0);
+
+const TEMPO: S = S(13, 13);
+
+// Variant terms. Crazyhouse pieces in hand by role, three-check bonus by checks given,
+// king of the hill by king distance to the center, racing kings by king rank.
+const PIECE_IN_HAND: [S; 7] = [S(0, 0), S(120, 100), S(330, 300), S(340, 320), S(480, 500), S(950, 1000), S(0, 0)];
+const CHECKS_GIVEN: [S; 4] = [S(0, 0), S(120, 120), S(400, 400), S(0, 0)];
+const KING_TO_CENTER: [S; 8] = [S(0, 0), S(150, 250), S(60, 120), S(20, 50), S(0, 0), S(0, 0), S(0, 0), S(0, 0)];
+const KING_RACE: [S; 8] = [S(0, 0), S(15, 15), S(35, 35), S(60, 60), S(90, 90), S(130, 130), S(200, 200), S(0, 0)];
+
+// Piece values for material evaluation, indexed by role
+pub const PIECE_VALUES: [i16; 7] = [0, 100, 325, 350, 500, 1000, 10000];
+
 pub fn get_piece_value(role: Role) -> Score {
-    Score::Centipawn(match role {
-        Role::Pawn => 100,
-        Role::Knight => 325,
-        Role::Bishop => 350,
-        Role::Rook => 500,
-        Role::Queen => 1000,
-        Role::King => 10000,
-    })
+    Score::Centipawn(PIECE_VALUES[role as usize])
 }
 
 
-// Helper function to get positional bonus with phase interpolation
-fn get_positional_bonus(piece: Piece, square: Square, phase: f32) -> Score {
+// Helper function to get the (mg, eg) positional bonus
+fn get_positional_bonus(piece: Piece, square: Square) -> S {
     let (mg_table, eg_table) = match piece.role {
         Role::Pawn => (&PAWN_MG, &PAWN_EG),
         Role::Knight => (&KNIGHT_MG, &KNIGHT_EG),
@@ -170,13 +249,11 @@ fn get_positional_bonus(piece: Piece, square: Square, phase: f32) -> Score {
 
     let index: usize = rank * 8 + file;
     
-    let mg: i16 = mg_table[index];
-    let eg: i16 = eg_table[index];
-    
-    Score::Centipawn((mg as f32 * phase + eg as f32 * (1.0 - phase)) as i16)
+    S(mg_table[index], eg_table[index])
 }
 
-pub fn calculate_score(pos: &Chess) -> Score {
+pub fn calculate_score<P: EnginePosition>(pos: &P, pawn_table: &mut PawnTable) -> Score {
+    // Outcome includes variant wins such as a king reaching the hill
     if pos.is_game_over() {
         return match pos.outcome().unwrap() {
             Outcome::Decisive { winner } => Score::Mate(1).apply_color_factor(winner),
@@ -184,53 +261,127 @@ pub fn calculate_score(pos: &Chess) -> Score {
         };
     }
 
-    let mut score = Score::ZERO;
+    evaluate_total(pos, pawn_table, |_, _| {}).taper(calculate_game_phase(pos.board()))
+}
+
+// Per-term (mg, eg) breakdown of the evaluation from white's point of view, followed by
+// the total, along with the game phase used to interpolate it
+pub fn evaluation_breakdown<P: EnginePosition>(pos: &P, pawn_table: &mut PawnTable) -> (Vec<(&'static str, S)>, i32) {
+    let mut terms = Vec::new();
+    let total = evaluate_total(pos, pawn_table, |name, score| terms.push((name, score)));
+    terms.push(("Total", total));
+    (terms, calculate_game_phase(pos.board()))
+}
+
+// The terms, then a specialized endgame evaluation replacing them or the endgame part
+// scaled down for drawish material. Both corrections are traced as terms of their own.
+fn evaluate_total<P: EnginePosition>(pos: &P, pawn_table: &mut PawnTable, mut trace: impl FnMut(&'static str, S)) -> S {
+    let score = evaluate_terms(pos, pawn_table, &mut trace);
+    let Some(chess) = pos.as_chess() else {
+        return score;
+    };
+
+    if let Some(Score::Centipawn(value)) = evaluate_endgame(chess) {
+        let endgame = S(value, value);
+        trace("Endgame", endgame - score);
+        return endgame;
+    }
+
+    let strong = if score.eg() > 0 { Color::White } else { Color::Black };
+    let scale = scale_factor(chess.board(), strong);
+    if scale == SCALE_FACTOR_NORMAL {
+        return score;
+    }
+    let scaled = S(score.mg(), (score.eg() as i32 * scale / SCALE_FACTOR_NORMAL) as i16);
+    trace("Scale", scaled - score);
+    scaled
+}
+
+fn evaluate_terms<P: EnginePosition>(pos: &P, pawn_table: &mut PawnTable, mut trace: impl FnMut(&'static str, S)) -> S {
+    let mut score = S::ZERO;
+    let mut add = |name: &'static str, term: S| {
+        trace(name, term);
+        score += term;
+    };
     let board = pos.board();
-    let game_phase = calculate_game_phase(board);
 
-    // Material and positional evaluation
+    // Material and positional evaluation, kings cancel out
+    let mut material = S::ZERO;
     for square in board.occupied() {
         let piece = board.piece_at(square).unwrap();
-        let value = get_piece_value(piece.role);
-        let positional = get_positional_bonus(piece, square, game_phase);
-        score += (value + positional).apply_color_factor(piece.color);
+        let value = if piece.role == Role::King { 0 } else { PIECE_VALUES[piece.role as usize] };
+        let positional = get_positional_bonus(piece, square);
+        material += (S(value, value) + positional).apply_color_factor(piece.color);
+    }
+    // Losing all pieces is the goal in antichess
+    if pos.variant() == Variant::Antichess {
+        material = -material;
     }
+    add("Material", material);
 
     // Pawn structure evaluation
-    score += evaluate_pawn_structure(board);
+    let pawns = probe_pawn_structure(board, pawn_table);
+    add("Pawn structure", pawns.score);
+
+    // Passed pawns
+    add("Passed pawns", evaluate_passed_pawns(board, &pawns));
 
     // Mobility evaluation
-    score += evaluate_mobility(pos);
+    add("Mobility", evaluate_mobility(board, &pawns));
 
     // King safety
-    score += evaluate_king_safety(board, game_phase);
+    add("King safety", evaluate_king_safety(board, &pawns));
+
+    // Piece-specific terms
+    add("Rooks on files", evaluate_rook_files(board, &pawns));
+    add("Rooks on 7th", evaluate_rooks_on_seventh(board));
+    add("Outposts", evaluate_outposts(board, &pawns));
+    add("Bad bishops", evaluate_bad_bishops(board));
+    add("Trapped pieces", evaluate_trapped_pieces(pos));
+    add("Queen development", evaluate_queen_development(board));
 
     // Space
-    score += evaluate_space_control(board, game_phase);
+    add("Space", evaluate_space_control(board));
 
     // Center control
-    score += evaluate_center_control(board, game_phase);
+    add("Center control", evaluate_center_control(board));
 
     // Tempo
-    score += Score::Centipawn(13).apply_color_factor(pos.turn());
+    add("Tempo", TEMPO.apply_color_factor(pos.turn()));
+
+    if pos.variant() != Variant::Chess {
+        add("Variant", evaluate_variant(pos));
+    }
 
     score
 }
 
 // Helper functions implementation
-fn calculate_game_phase(board: &Board) -> f32 {
-    let current_phase = 
-        board.knights().count() as f32 * 1.0 +
-        board.bishops().count() as f32 * 1.0 +
-        board.rooks().count() as f32 * 2.0 +
-        board.queens().count() as f32 * 4.0;
-    (current_phase / 24.0).clamp(0.0, 1.0)
-}
-
-fn evaluate_pawn_structure(board: &shakmaty::Board) -> Score {
-    let mut score = 0;
-    for color in &[Color::White, Color::Black] {
-        let pawns = board.by_piece(Piece { color: *color, role: Role::Pawn });
+fn calculate_game_phase(board: &Board) -> i32 {
+    let current_phase =
+        board.knights().count() as i32 +
+        board.bishops().count() as i32 +
+        board.rooks().count() as i32 * 2 +
+        board.queens().count() as i32 * 4;
+    current_phase.min(MAX_PHASE)
+}
+
+fn probe_pawn_structure(board: &Board, pawn_table: &mut PawnTable) -> PawnEntry {
+    let key = pawn_key(board);
+    if let Some(entry) = pawn_table.get(key) {
+        return *entry;
+    }
+    let entry = evaluate_pawn_structure(board, key);
+    pawn_table.insert(entry);
+    entry
+}
+
+fn evaluate_pawn_structure(board: &Board, key: u64) -> PawnEntry {
+    let mut entry = PawnEntry { key, ..Default::default() };
+    let mut score = S::ZERO;
+    for color in [Color::White, Color::Black] {
+        let pawns = board.by_piece(Piece { color, role: Role::Pawn });
+        let mut color_score = S::ZERO;
         let mut files = [0u8; 8];
         
         for sq in pawns {
@@ -238,74 +389,470 @@ fn evaluate_pawn_structure(board: &shakmaty::Board) -> Score {
             // Isolated pawn check
             if (sq.file() == File::A || files[(sq.file() as usize).saturating_sub(1)] == 0) &&
                (sq.file() == File::H || files[(sq.file() as usize) + 1] == 0) {
-                score += if *color == Color::White { -15 } else { 15 };
+                color_score -= ISOLATED_PAWN;
             }
+            *entry.attacks.get_mut(color) |= attacks::pawn_attacks(color, sq);
+            *entry.attack_spans.get_mut(color) |= forward_ranks(color, sq) & adjacent_files(sq.file());
         }
         
         // Doubled pawns
         for &count in &files {
             if count > 1 {
-                score += -get_color_factor(*color) * 20 * (count as i16 - 1);
+                color_score -= DOUBLED_PAWN * (count as i32 - 1);
+            }
+        }
+
+        let passed = passed_pawns(board, color);
+        let connected = connected_passed_pawns(board, color);
+        for sq in passed {
+            let rank = color.relative_rank(sq.rank()) as usize;
+            color_score += PASSED_PAWN[rank];
+            if connected.contains(sq) {
+                color_score += CONNECTED_PASSER[rank];
             }
         }
+
+        for sq in candidate_passed_pawns(board, color) {
+            let rank = color.relative_rank(sq.rank()) as usize;
+            color_score += CANDIDATE_PASSER[rank];
+        }
+        score += color_score.apply_color_factor(color);
+
+        *entry.passed.get_mut(color) = passed;
+        *entry.semi_open_files.get_mut(color) = File::ALL
+            .into_iter()
+            .filter(|&f| (Bitboard::from_file(f) & pawns).is_empty())
+            .fold(Bitboard::EMPTY, |acc, f| acc | Bitboard::from_file(f));
     }
-    Score::Centipawn(score)
+    entry.open_files = entry.semi_open_files.white & entry.semi_open_files.black;
+    entry.score = score;
+    entry
 }
 
-fn evaluate_mobility(pos: &Chess) -> Score {
-    let mut white_mobility = 0;
-    let mut black_mobility = 0;
-    let board = pos.board();
-    
-    // Evaluate white's mobility
-    for sq in board.by_color(Color::White) {
-        if let Some(piece) = board.piece_at(sq) {
-            let attacks = board.attacks_from(sq);
-            let mob = (attacks.count() as i16) * match piece.role {
-                Role::Knight => 2,
-                Role::Bishop => 3,
-                Role::Rook => 2,
-                Role::Queen => 1,
-                _ => 0,
-            };
-            white_mobility += mob;
+// Squares on the ranks strictly in front of the square, from the color's point of view
+fn forward_ranks(color: Color, square: Square) -> Bitboard {
+    let rank = square.rank() as u32;
+    match color {
+        Color::White if rank == 7 => Bitboard::EMPTY,
+        Color::White => Bitboard(!0u64 << (8 * (rank + 1))),
+        Color::Black => Bitboard((1u64 << (8 * rank)) - 1),
+    }
+}
+
+fn adjacent_files(file: File) -> Bitboard {
+    [file.offset(-1), file.offset(1)]
+        .into_iter()
+        .flatten()
+        .fold(Bitboard::EMPTY, |acc, f| acc | Bitboard::from_file(f))
+}
+
+// Squares in front of the pawn on its own file
+fn front_span(color: Color, square: Square) -> Bitboard {
+    forward_ranks(color, square) & Bitboard::from_file(square.file())
+}
+
+// Squares an enemy pawn must not occupy for the pawn to be passed
+fn passed_pawn_span(color: Color, square: Square) -> Bitboard {
+    forward_ranks(color, square) & (Bitboard::from_file(square.file()) | adjacent_files(square.file()))
+}
+
+fn promotion_square(color: Color, square: Square) -> Square {
+    Square::from_coords(square.file(), color.relative_rank(Rank::Eighth))
+}
+
+fn passed_pawns(board: &Board, color: Color) -> Bitboard {
+    let enemy_pawns = board.by_piece(Piece { color: !color, role: Role::Pawn });
+    board
+        .by_piece(Piece { color, role: Role::Pawn })
+        .into_iter()
+        .filter(|&sq| (passed_pawn_span(color, sq) & enemy_pawns).is_empty())
+        .collect()
+}
+
+// Pawns on a half-open file that have at least as many helpers as sentries
+fn candidate_passed_pawns(board: &Board, color: Color) -> Bitboard {
+    let own_pawns = board.by_piece(Piece { color, role: Role::Pawn });
+    let enemy_pawns = board.by_piece(Piece { color: !color, role: Role::Pawn });
+    own_pawns
+        .into_iter()
+        .filter(|&sq| {
+            if (front_span(color, sq) & board.pawns()).any() {
+                return false;
+            }
+            let sentries = passed_pawn_span(color, sq) & enemy_pawns;
+            if sentries.is_empty() {
+                return false;
+            }
+            let helpers = adjacent_files(sq.file()) & own_pawns & !forward_ranks(color, sq);
+            helpers.count() >= sentries.count()
+        })
+        .collect()
+}
+
+// Passed pawns with another passed pawn next to them, at most one rank apart
+fn connected_passed_pawns(board: &Board, color: Color) -> Bitboard {
+    let passers = passed_pawns(board, color);
+    passers
+        .into_iter()
+        .filter(|&sq| {
+            (adjacent_files(sq.file()) & passers)
+                .into_iter()
+                .any(|other| other.rank().distance(sq.rank()) <= 1)
+        })
+        .collect()
+}
+
+fn king_proximity_bonus(board: &Board, color: Color, square: Square) -> i16 {
+    let promotion = promotion_square(color, square);
+    let weight = PASSER_RANK_WEIGHT[color.relative_rank(square.rank()) as usize];
+    let own = board.king_of(color).map_or(0, |k| k.distance(promotion) as i16);
+    let enemy = board.king_of(!color).map_or(0, |k| k.distance(promotion) as i16);
+    (enemy * ENEMY_KING_DISTANCE_EG - own * OWN_KING_DISTANCE_EG) * weight
+}
+
+// Free and safe path to the promotion square, or a penalty when blockaded
+fn passer_path_bonus(board: &Board, color: Color, square: Square) -> i16 {
+    let weight = PASSER_RANK_WEIGHT[color.relative_rank(square.rank()) as usize];
+    let path = front_span(color, square);
+    if (path & board.occupied()).is_empty() {
+        let attacked = path
+            .into_iter()
+            .any(|sq| board.attacks_to(sq, !color, board.occupied()).any());
+        if attacked {
+            FREE_PATH_EG * weight
+        } else {
+            SAFE_PATH_EG * weight
+        }
+    } else {
+        let blockaded = square
+            .offset(if color == Color::White { 8 } else { -8 })
+            .is_some_and(|sq| board.by_color(!color).contains(sq));
+        if blockaded {
+            -BLOCKADED_PASSER_EG * weight
+        } else {
+            0
         }
     }
-    
-    // Evaluate black's mobility
-    for sq in board.by_color(Color::Black) {
-        if let Some(piece) = board.piece_at(sq) {
-            let attacks = board.attacks_from(sq);
-            let mob = (attacks.count() as i16) * match piece.role {
-                Role::Knight => 2,
-                Role::Bishop => 3,
-                Role::Rook => 2,
-                Role::Queen => 1,
-                _ => 0,
-            };
-            black_mobility += mob;
+}
+
+// Rooks belong behind passed pawns, both own and enemy ones
+fn rook_behind_passer_bonus(board: &Board, color: Color, square: Square) -> i16 {
+    let behind = front_span(!color, square);
+    let mut bonus = 0;
+    for rook_sq in behind & board.rooks() {
+        if (attacks::between(rook_sq, square) & board.occupied()).is_empty() {
+            let rook_color = board.color_at(rook_sq).unwrap();
+            bonus += if rook_color == color { ROOK_BEHIND_PASSER_EG } else { -ROOK_BEHIND_PASSER_EG };
         }
     }
-    
-    // Net mobility is white's minus black's
-    Score::Centipawn(white_mobility - black_mobility)
+    bonus
 }
 
-fn evaluate_king_safety(board: &shakmaty::Board, phase: f32) -> Score {
-    let mut score = 0;
-    for color in &[Color::White, Color::Black] {
-        if let Some(sq) = board.king_of(*color) {
-            let penalty = match sq.file() {
-                File::D | File::E => (50.0 * phase) as i16,
-                _ => (20.0 * phase) as i16,
+// Passed pawn terms that depend on pieces, on top of the cached pawn-only bonuses
+fn evaluate_passed_pawns(board: &Board, pawns: &PawnEntry) -> S {
+    let mut eg = 0;
+    for color in [Color::White, Color::Black] {
+        let factor = get_color_factor(color);
+        for sq in *pawns.passed.get(color) {
+            eg += factor * passer_path_bonus(board, color, sq);
+            eg += factor * king_proximity_bonus(board, color, sq);
+            eg += factor * rook_behind_passer_bonus(board, color, sq);
+        }
+    }
+    S(0, eg)
+}
+
+// Squares worth counting for mobility: not occupied by own pieces (which covers
+// the own king and blocked pawns) and not attacked by enemy pawns
+fn mobility_area(board: &Board, pawns: &PawnEntry, color: Color) -> Bitboard {
+    !board.by_color(color) & !*pawns.attacks.get(!color)
+}
+
+fn evaluate_mobility(board: &Board, pawns: &PawnEntry) -> S {
+    let mut score = S::ZERO;
+    for color in [Color::White, Color::Black] {
+        let area = mobility_area(board, pawns, color);
+        for sq in board.by_color(color) & !board.pawns() & !board.kings() {
+            let table: &[S] = match board.role_at(sq).unwrap() {
+                Role::Knight => &KNIGHT_MOBILITY,
+                Role::Bishop => &BISHOP_MOBILITY,
+                Role::Rook => &ROOK_MOBILITY,
+                _ => &QUEEN_MOBILITY,
             };
-            score += if *color == Color::White { -penalty } else { penalty };
+            let count = (board.attacks_from(sq) & area).count();
+            score += table[count.min(table.len() - 1)].apply_color_factor(color);
+        }
+    }
+    score
+}
+
+// All squares attacked by the pieces of one side
+fn attacked_by(board: &Board, color: Color) -> Bitboard {
+    board
+        .by_color(color)
+        .into_iter()
+        .fold(Bitboard::EMPTY, |acc, sq| acc | board.attacks_from(sq))
+}
+
+// Squares around the king plus one more rank towards the enemy
+fn king_zone(color: Color, king: Square) -> Bitboard {
+    let zone = attacks::king_attacks(king) | Bitboard::from_square(king);
+    zone | zone.shift(if color == Color::White { 8 } else { -8 })
+}
+
+// Shelter, storm and open file units for the three files around the king
+fn king_shelter_units(board: &Board, pawns: &PawnEntry, color: Color, king: Square) -> usize {
+    let own_pawns = board.by_piece(Piece { color, role: Role::Pawn });
+    let enemy_pawns = board.by_piece(Piece { color: !color, role: Role::Pawn });
+    let king_rank = color.relative_rank(king.rank()) as i32;
+    let mut units = 0;
+
+    let files = [king.file().offset(-1), Some(king.file()), king.file().offset(1)];
+    for file in files.into_iter().flatten() {
+        let file_bb = Bitboard::from_file(file);
+
+        // Pawn shield: closest own pawn in front of the king on this file
+        let shield = (own_pawns & file_bb)
+            .into_iter()
+            .map(|sq| color.relative_rank(sq.rank()) as i32 - king_rank)
+            .filter(|&dist| dist > 0)
+            .min();
+        units += match shield {
+            Some(1) => 0,
+            Some(2) => SHIELD_UNITS[1],
+            _ => SHIELD_UNITS[2],
+        };
+
+        // Pawn storm: enemy pawns approaching the king
+        let storm = (enemy_pawns & file_bb)
+            .into_iter()
+            .map(|sq| color.relative_rank(sq.rank()) as i32 - king_rank)
+            .filter(|&dist| dist > 0)
+            .min();
+        if let Some(dist @ 1..=3) = storm {
+            units += STORM_UNITS[dist as usize];
         }
+
+        // Open and semi-open files next to the king
+        if pawns.open_files.is_superset(file_bb) {
+            units += OPEN_FILE_UNITS;
+        } else if pawns.semi_open_files.get(color).is_superset(file_bb) {
+            units += SEMI_OPEN_FILE_UNITS;
+        }
+    }
+    units
+}
+
+// Attack units collected by the enemy pieces against the king of the given color
+fn king_attack_units(board: &Board, color: Color, king: Square) -> usize {
+    let enemy = !color;
+    let zone = king_zone(color, king);
+    let occupied = board.occupied();
+    let defended = attacked_by(board, color);
+    let mut attackers = 0;
+    let mut units = 0;
+
+    for sq in board.by_color(enemy) & !board.pawns() & !board.kings() {
+        let role = board.role_at(sq).unwrap();
+        let piece_attacks = board.attacks_from(sq);
+
+        let zone_attacks = piece_attacks & zone;
+        if zone_attacks.any() {
+            attackers += 1;
+            units += zone_attacks.count() * KING_ATTACK_WEIGHT[role as usize];
+        }
+
+        // Checks the piece could give on a square we do not defend
+        let check_squares = match role {
+            Role::Knight => attacks::knight_attacks(king),
+            Role::Bishop => attacks::bishop_attacks(king, occupied),
+            Role::Rook => attacks::rook_attacks(king, occupied),
+            Role::Queen => attacks::queen_attacks(king, occupied),
+            _ => Bitboard::EMPTY,
+        };
+        let safe_checks = piece_attacks & check_squares & !board.by_color(enemy) & !defended;
+        if safe_checks.any() {
+            units += SAFE_CHECK_UNITS[role as usize];
+        }
+    }
+
+    // A lone attacker rarely mates
+    if attackers < 2 && (board.queens() & board.by_color(enemy)).is_empty() {
+        units /= 2;
     }
-    Score::Centipawn(score)
+    units
 }
 
-fn evaluate_space_control(board: &Board, phase: f32) -> Score {
+fn evaluate_king_safety(board: &Board, pawns: &PawnEntry) -> S {
+    let mut mg = 0;
+    for color in [Color::White, Color::Black] {
+        if let Some(king) = board.king_of(color) {
+            let units = king_attack_units(board, color, king) + king_shelter_units(board, pawns, color, king);
+            mg -= get_color_factor(color) * KING_SAFETY_TABLE[units.min(KING_SAFETY_TABLE.len() - 1)];
+        }
+    }
+    S(mg, 0)
+}
+
+// Sums a per-piece term for both sides
+fn evaluate_pieces(board: &Board, role: Role, mut term: impl FnMut(Color, Square) -> S) -> S {
+    let mut score = S::ZERO;
+    for color in [Color::White, Color::Black] {
+        for sq in board.by_piece(Piece { color, role }) {
+            score += term(color, sq).apply_color_factor(color);
+        }
+    }
+    score
+}
+
+fn evaluate_rook_files(board: &Board, pawns: &PawnEntry) -> S {
+    evaluate_pieces(board, Role::Rook, |color, sq| {
+        if pawns.open_files.contains(sq) {
+            ROOK_OPEN_FILE
+        } else if pawns.semi_open_files.get(color).contains(sq) {
+            ROOK_SEMI_OPEN_FILE
+        } else {
+            S::ZERO
+        }
+    })
+}
+
+// Rook on the 7th rank, when it cuts off the king or attacks pawns there
+fn evaluate_rooks_on_seventh(board: &Board) -> S {
+    evaluate_pieces(board, Role::Rook, |color, sq| {
+        if color.relative_rank(sq.rank()) != Rank::Seventh {
+            return S::ZERO;
+        }
+        let enemy_pawns = board.by_piece(Piece { color: !color, role: Role::Pawn });
+        let king_on_back_rank = board
+            .king_of(!color)
+            .is_some_and(|k| color.relative_rank(k.rank()) == Rank::Eighth);
+        if king_on_back_rank || (enemy_pawns & Bitboard::from_rank(sq.rank())).any() {
+            ROOK_ON_SEVENTH
+        } else {
+            S::ZERO
+        }
+    })
+}
+
+// Minor pieces on squares protected by own pawns that enemy pawns can never attack
+fn evaluate_outposts(board: &Board, pawns: &PawnEntry) -> S {
+    let is_outpost = |color: Color, sq: Square| {
+        let rank = color.relative_rank(sq.rank());
+        (Rank::Fourth..=Rank::Sixth).contains(&rank)
+            && pawns.attacks.get(color).contains(sq)
+            && !pawns.attack_spans.get(!color).contains(sq)
+    };
+    evaluate_pieces(board, Role::Knight, |color, sq| {
+        if is_outpost(color, sq) { KNIGHT_OUTPOST } else { S::ZERO }
+    }) + evaluate_pieces(board, Role::Bishop, |color, sq| {
+        if is_outpost(color, sq) { BISHOP_OUTPOST } else { S::ZERO }
+    })
+}
+
+// Bishops hemmed in by own pawns on their square color
+fn evaluate_bad_bishops(board: &Board) -> S {
+    evaluate_pieces(board, Role::Bishop, |color, sq| {
+        let same_color = if sq.is_light() { Bitboard::LIGHT_SQUARES } else { Bitboard::DARK_SQUARES };
+        let blockers = (board.by_piece(Piece { color, role: Role::Pawn }) & same_color).count() as i32;
+        -BAD_BISHOP_PAWN * blockers
+    })
+}
+
+// Bishops caught by a pawn on a7/h7 style squares and rooks boxed in by an uncastled king
+fn evaluate_trapped_pieces<P: Position>(pos: &P) -> S {
+    let board = pos.board();
+    let bishops = evaluate_pieces(board, Role::Bishop, |color, sq| {
+        let relative = if color == Color::White { sq } else { sq.flip_vertical() };
+        let trap = match relative {
+            Square::A7 => Square::B6,
+            Square::H7 => Square::G6,
+            Square::A6 => Square::B5,
+            Square::H6 => Square::G5,
+            _ => return S::ZERO,
+        };
+        let trap = if color == Color::White { trap } else { trap.flip_vertical() };
+        if board.piece_at(trap) == Some(Piece { color: !color, role: Role::Pawn }) {
+            if relative.rank() == Rank::Seventh {
+                -TRAPPED_BISHOP
+            } else {
+                S(-TRAPPED_BISHOP.mg() / 2, -TRAPPED_BISHOP.eg() / 2)
+            }
+        } else {
+            S::ZERO
+        }
+    });
+    let rooks = evaluate_pieces(board, Role::Rook, |color, sq| {
+        let Some(king) = board.king_of(color) else {
+            return S::ZERO;
+        };
+        let back_rank = color.relative_rank(Rank::First);
+        if sq.rank() != back_rank || king.rank() != back_rank || board.attacks_from(sq).count() > 3 {
+            return S::ZERO;
+        }
+        let kingside = king.file() >= File::E && sq.file() > king.file();
+        let queenside = king.file() <= File::D && sq.file() < king.file();
+        let can_castle = (kingside && pos.castles().has(color, CastlingSide::KingSide))
+            || (queenside && pos.castles().has(color, CastlingSide::QueenSide));
+        if (kingside || queenside) && !can_castle {
+            -TRAPPED_ROOK
+        } else {
+            S::ZERO
+        }
+    });
+    bishops + rooks
+}
+
+// Queen out before the minor pieces are developed
+fn evaluate_queen_development(board: &Board) -> S {
+    evaluate_pieces(board, Role::Queen, |color, sq| {
+        let home = if color == Color::White { Square::D1 } else { Square::D8 };
+        if sq == home {
+            return S::ZERO;
+        }
+        let minor_homes = [Square::B1, Square::C1, Square::F1, Square::G1]
+            .map(|sq| if color == Color::White { sq } else { sq.flip_vertical() });
+        let undeveloped = minor_homes
+            .into_iter()
+            .filter(|&sq| {
+                matches!(board.piece_at(sq), Some(p) if p.color == color && matches!(p.role, Role::Knight | Role::Bishop))
+            })
+            .count() as i32;
+        -QUEEN_EARLY_DEVELOPMENT * undeveloped
+    })
+}
+
+fn evaluate_variant<P: EnginePosition>(pos: &P) -> S {
+    let board = pos.board();
+    let mut score = S::ZERO;
+    for color in Color::ALL {
+        let mut term = S::ZERO;
+        if let Some(pockets) = pos.pockets() {
+            for role in Role::ALL {
+                term += PIECE_IN_HAND[role as usize] * *pockets.get(color).get(role) as i32;
+            }
+        }
+        if let Some(remaining) = pos.remaining_checks() {
+            let given = 3 - u32::from(*remaining.get(color)).min(3);
+            term += CHECKS_GIVEN[given as usize];
+        }
+        if let Some(king) = board.king_of(color) {
+            match pos.variant() {
+                Variant::KingOfTheHill => {
+                    let center = [Square::D4, Square::E4, Square::D5, Square::E5];
+                    let distance = center.into_iter().map(|sq| king.distance(sq)).min().unwrap();
+                    term += KING_TO_CENTER[distance as usize];
+                }
+                Variant::RacingKings => term += KING_RACE[king.rank() as usize],
+                _ => {}
+            }
+        }
+        score += term.apply_color_factor(color);
+    }
+    score
+}
+
+fn evaluate_space_control(board: &Board) -> S {
     let mut white_space = 0;
     let mut black_space = 0;
 
@@ -354,12 +901,11 @@ fn evaluate_space_control(board: &Board, phase: f32) -> Score {
         }
     }
 
-    // Apply phase scaling (space is more important in middlegame)
-    let space_score = ((white_space as f32 - black_space as f32) * phase) as i16;
-    Score::Centipawn(space_score)
+    // Middlegame only (space is more important in middlegame)
+    S(white_space - black_space, 0)
 }
 
-fn evaluate_center_control(board: &Board, phase: f32) -> Score {
+fn evaluate_center_control(board: &Board) -> S {
     // Define central squares (d4, e4, d5, e5)
     const CENTER_SQUARES: [Square; 4] = [
         Square::D4,
@@ -420,7 +966,285 @@ fn evaluate_center_control(board: &Board, phase: f32) -> Score {
         }
     }
 
-    // Apply phase scaling (center control matters more in opening/middlegame)
-    let net_control = ((white_control - black_control) as f32 * phase) as i16;
-    Score::Centipawn(net_control)
-}
\ No newline at end of file
+    // Middlegame only (center control matters more in opening/middlegame)
+    S(white_control - black_control, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::position;
+    use shakmaty::{fen::Fen, variant::VariantPosition, CastlingMode};
+
+    fn board(fen: &str) -> Board {
+        let pos = position(fen);
+        pos.board().clone()
+    }
+
+    #[test]
+    fn test_passed_pawns() {
+        let board = board("4k3/8/8/3p4/8/2P5/P7/4K3 w - - 0 1");
+        assert_eq!(passed_pawns(&board, Color::White), Bitboard::from_square(Square::A2));
+        assert_eq!(passed_pawns(&board, Color::Black), Bitboard::EMPTY);
+    }
+
+    #[test]
+    fn test_candidate_passed_pawns() {
+        // c4 has a helper on b4 against a single sentry on d5
+        let supported = board("4k3/8/8/3p4/1PP5/8/8/4K3 w - - 0 1");
+        assert!(candidate_passed_pawns(&supported, Color::White).contains(Square::C4));
+        // Two sentries against one helper
+        let outnumbered = board("4k3/8/8/1p1p4/2P5/3P4/8/4K3 w - - 0 1");
+        assert!(!candidate_passed_pawns(&outnumbered, Color::White).contains(Square::C4));
+    }
+
+    #[test]
+    fn test_connected_passed_pawns() {
+        let board = board("4k3/8/8/3PP3/8/8/P7/4K3 w - - 0 1");
+        let connected = connected_passed_pawns(&board, Color::White);
+        assert!(connected.contains(Square::D5));
+        assert!(connected.contains(Square::E5));
+        assert!(!connected.contains(Square::A2));
+    }
+
+    fn pawn_structure(fen: &str) -> PawnEntry {
+        let board = board(fen);
+        evaluate_pawn_structure(&board, pawn_key(&board))
+    }
+
+    #[test]
+    fn test_passed_pawn_bonus_grows_with_rank() {
+        let far = pawn_structure("7k/8/8/8/8/P7/8/7K w - - 0 1");
+        let near = pawn_structure("7k/8/P7/8/8/8/8/7K w - - 0 1");
+        assert!(near.score.eg() > far.score.eg());
+        assert!(near.score.mg() > far.score.mg());
+    }
+
+    #[test]
+    fn test_passer_path_bonus() {
+        let free = board("k7/8/8/3P4/8/8/8/K7 w - - 0 1");
+        let attacked = board("k7/8/5n2/3P4/8/8/8/K7 w - - 0 1");
+        let blockaded = board("k7/8/3n4/3P4/8/8/8/K7 w - - 0 1");
+        let safe = passer_path_bonus(&free, Color::White, Square::D5);
+        let unsafe_path = passer_path_bonus(&attacked, Color::White, Square::D5);
+        assert!(safe > unsafe_path);
+        assert!(unsafe_path > 0);
+        assert!(passer_path_bonus(&blockaded, Color::White, Square::D5) < 0);
+    }
+
+    #[test]
+    fn test_king_proximity_bonus() {
+        let escorted = board("k7/8/4K3/3P4/8/8/8/8 w - - 0 1");
+        let caught = board("8/3k4/8/3P4/8/8/8/7K w - - 0 1");
+        assert!(
+            king_proximity_bonus(&escorted, Color::White, Square::D5)
+                > king_proximity_bonus(&caught, Color::White, Square::D5)
+        );
+    }
+
+    #[test]
+    fn test_rook_behind_passer_bonus() {
+        let own = board("k7/8/8/3P4/8/8/8/K2R4 w - - 0 1");
+        let enemy = board("K7/8/8/3P4/8/8/8/k2r4 w - - 0 1");
+        let blocked = board("k7/8/8/3P4/8/3N4/8/K2R4 w - - 0 1");
+        assert_eq!(rook_behind_passer_bonus(&own, Color::White, Square::D5), ROOK_BEHIND_PASSER_EG);
+        assert_eq!(rook_behind_passer_bonus(&enemy, Color::White, Square::D5), -ROOK_BEHIND_PASSER_EG);
+        assert_eq!(rook_behind_passer_bonus(&blocked, Color::White, Square::D5), 0);
+    }
+
+    #[test]
+    fn test_passed_pawns_symmetric() {
+        let white_board = board("4k3/8/8/8/3P4/8/8/4K3 w - - 0 1");
+        let black_board = board("4k3/8/8/3p4/8/8/8/4K3 w - - 0 1");
+        let white = pawn_structure("4k3/8/8/8/3P4/8/8/4K3 w - - 0 1");
+        let black = pawn_structure("4k3/8/8/3p4/8/8/8/4K3 w - - 0 1");
+        assert_eq!(white.score, -black.score);
+        assert_eq!(
+            evaluate_passed_pawns(&white_board, &white),
+            -evaluate_passed_pawns(&black_board, &black)
+        );
+    }
+
+    #[test]
+    fn test_pawn_structure_files_and_spans() {
+        let entry = pawn_structure("4k3/p7/8/8/8/8/P3P3/4K3 w - - 0 1");
+        assert!(entry.open_files.contains(Square::B4));
+        assert!(!entry.open_files.contains(Square::A4));
+        assert!(entry.semi_open_files.black.contains(Square::E4));
+        assert!(!entry.semi_open_files.white.contains(Square::E4));
+        assert!(entry.attacks.white.contains(Square::D3));
+        assert!(entry.attack_spans.white.contains(Square::F7));
+        assert!(!entry.attack_spans.white.contains(Square::E7));
+    }
+
+    #[test]
+    fn test_pawn_table_hit() {
+        let mut table = PawnTable::new(1024);
+        let first = board("r3k3/pp6/8/8/8/8/PP6/4K2R w - - 0 1");
+        let same_pawns = board("4k3/pp6/8/8/8/8/PP6/R3K3 w - - 0 1");
+        assert_eq!(pawn_key(&first), pawn_key(&same_pawns));
+        let fresh = probe_pawn_structure(&first, &mut table);
+        let cached = probe_pawn_structure(&same_pawns, &mut table);
+        assert_eq!((fresh.score, fresh.passed), (cached.score, cached.passed));
+        assert_eq!(table.hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn test_king_shelter_units() {
+        let sheltered = board("6k1/8/8/8/8/8/5PPP/6K1 w - - 0 1");
+        let exposed = board("6k1/8/8/8/8/8/8/6K1 w - - 0 1");
+        let stormed = board("6k1/8/8/8/8/6p1/5P1P/6K1 w - - 0 1");
+        let units = |board: &Board| {
+            let pawns = evaluate_pawn_structure(board, pawn_key(board));
+            king_shelter_units(board, &pawns, Color::White, Square::G1)
+        };
+        assert_eq!(units(&sheltered), 0);
+        assert!(units(&exposed) > units(&stormed));
+        assert!(units(&stormed) > units(&sheltered));
+    }
+
+    #[test]
+    fn test_king_attack_units() {
+        let quiet = board("r5k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1");
+        let attacked = board("6k1/5ppp/8/8/8/5n2/5PPq/6K1 w - - 0 1");
+        assert!(king_attack_units(&attacked, Color::White, Square::G1) > king_attack_units(&quiet, Color::White, Square::G1));
+    }
+
+    #[test]
+    fn test_rook_files() {
+        let open = board("4k3/p7/8/8/8/8/P7/3RK3 w - - 0 1");
+        let semi_open = board("4k3/3p4/8/8/8/8/P7/3RK3 w - - 0 1");
+        let closed = board("4k3/3p4/8/8/8/8/3P4/3RK3 w - - 0 1");
+        let score = |board: &Board| evaluate_rook_files(board, &evaluate_pawn_structure(board, pawn_key(board))).mg();
+        assert!(score(&open) > score(&semi_open));
+        assert!(score(&semi_open) > score(&closed));
+        assert_eq!(score(&closed), 0);
+    }
+
+    #[test]
+    fn test_rooks_on_seventh() {
+        let cutting_off = board("6k1/R7/8/8/8/8/8/6K1 w - - 0 1");
+        let pointless = board("8/R7/8/8/6k1/8/8/6K1 w - - 0 1");
+        assert!(evaluate_rooks_on_seventh(&cutting_off).eg() > 0);
+        assert_eq!(evaluate_rooks_on_seventh(&pointless), S::ZERO);
+    }
+
+    #[test]
+    fn test_outposts() {
+        let outpost = board("4k3/8/2p5/4N3/3P4/8/8/4K3 w - - 0 1");
+        let chased = board("4k3/5p2/2p5/4N3/3P4/8/8/4K3 w - - 0 1");
+        let score = |board: &Board| evaluate_outposts(board, &evaluate_pawn_structure(board, pawn_key(board))).mg();
+        assert!(score(&outpost) > 0);
+        assert_eq!(score(&chased), 0);
+    }
+
+    #[test]
+    fn test_bad_bishops() {
+        let bad = board("4k3/8/8/8/3P4/2P1P3/8/2B1K3 w - - 0 1");
+        let good = board("4k3/8/8/8/2P1P3/3P4/8/2B1K3 w - - 0 1");
+        assert!(evaluate_bad_bishops(&good).eg() > evaluate_bad_bishops(&bad).eg());
+    }
+
+    #[test]
+    fn test_trapped_pieces() {
+        let trapped_bishop = position("4k3/B7/1pp5/8/8/8/8/4K3 w - - 0 1");
+        let free_bishop = position("4k3/B7/2p5/8/8/8/8/4K3 w - - 0 1");
+        assert!(evaluate_trapped_pieces(&trapped_bishop).mg() < 0);
+        assert_eq!(evaluate_trapped_pieces(&free_bishop), S::ZERO);
+
+        let trapped_rook = position("4k3/8/8/8/8/8/5PPP/5K1R w - - 0 1");
+        let castling_rook = position("4k3/8/8/8/8/8/5PPP/4K2R w K - 0 1");
+        assert!(evaluate_trapped_pieces(&trapped_rook).mg() < 0);
+        assert_eq!(evaluate_trapped_pieces(&castling_rook), S::ZERO);
+    }
+
+    #[test]
+    fn test_queen_development() {
+        let early = board("rnbqkbnr/pppppppp/8/8/7Q/4P3/PPPP1PPP/RNB1KBNR w KQkq - 0 1");
+        let home = board("rnbqkbnr/pppppppp/8/8/8/4P3/PPPP1PPP/RNBQKBNR w KQkq - 0 1");
+        assert!(evaluate_queen_development(&early).mg() < 0);
+        assert_eq!(evaluate_queen_development(&home), S::ZERO);
+    }
+
+    #[test]
+    fn test_breakdown_sums_to_score() {
+        let pos = position("r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQK2R w KQkq - 0 1");
+        let (breakdown, phase) = evaluation_breakdown(&pos, &mut PawnTable::new(16));
+        let (name, total) = *breakdown.last().unwrap();
+        assert_eq!(name, "Total");
+        assert_eq!(total.taper(phase), calculate_score(&pos, &mut PawnTable::new(16)));
+        let sum = breakdown[..breakdown.len() - 1].iter().fold(S::ZERO, |acc, &(_, term)| acc + term);
+        assert_eq!(sum, total);
+        assert!(breakdown.iter().any(|&(name, _)| name == "Outposts"));
+
+        // Specialized and scaled endings, the opposite colored bishops last
+        for fen in ["8/8/8/4k3/8/8/8/2BNK3 w - - 0 1", "8/8/4k3/8/8/4P3/4K3/8 w - - 0 1", "8/5k2/8/2b5/4P3/3P1B2/5K2/8 w - - 0 1"] {
+            let pos = position(fen);
+            let (breakdown, phase) = evaluation_breakdown(&pos, &mut PawnTable::new(16));
+            let total = breakdown.last().unwrap().1;
+            assert_eq!(total.taper(phase), calculate_score(&pos, &mut PawnTable::new(16)));
+            let sum = breakdown[..breakdown.len() - 1].iter().fold(S::ZERO, |acc, &(_, term)| acc + term);
+            assert_eq!(sum, total);
+        }
+        let (breakdown, _) = evaluation_breakdown(&position("8/5k2/8/2b5/4P3/3P1B2/5K2/8 w - - 0 1"), &mut PawnTable::new(16));
+        assert!(breakdown.iter().any(|&(name, _)| name == "Scale"));
+    }
+
+    #[test]
+    fn test_mobility_area() {
+        let board = board("4k3/8/8/3p4/8/2N5/1P6/4K3 w - - 0 1");
+        let pawns = evaluate_pawn_structure(&board, pawn_key(&board));
+        let area = mobility_area(&board, &pawns, Color::White);
+        assert!(!area.contains(Square::B2));
+        assert!(!area.contains(Square::E1));
+        assert!(!area.contains(Square::E4));
+        assert!(area.contains(Square::D5));
+        assert!(area.contains(Square::A4));
+    }
+
+    #[test]
+    fn test_mobility_symmetric() {
+        let white = board("4k3/8/8/8/8/2N5/1P6/4K3 w - - 0 1");
+        let black = board("4k3/1p6/2n5/8/8/8/8/4K3 w - - 0 1");
+        let score = |board: &Board| evaluate_mobility(board, &evaluate_pawn_structure(board, pawn_key(board)));
+        assert!(score(&white).mg() > 0);
+        assert_eq!(score(&white), -score(&black));
+    }
+
+    fn variant_position(variant: Variant, fen: &str) -> VariantPosition {
+        let setup = fen.parse::<Fen>().unwrap().into_setup();
+        VariantPosition::from_setup(variant, setup, CastlingMode::Standard).unwrap()
+    }
+
+    fn variant_term(variant: Variant, fen: &str) -> S {
+        let (terms, _) = evaluation_breakdown(&variant_position(variant, fen), &mut PawnTable::new(1));
+        terms.into_iter().find(|(name, _)| *name == "Variant").map_or(S::ZERO, |(_, term)| term)
+    }
+
+    #[test]
+    fn test_variant_terms() {
+        // White holds a knight in hand
+        let pocket = variant_term(Variant::Crazyhouse, "rnbqkb1r/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[N] w KQkq - 0 1");
+        assert_eq!(pocket, PIECE_IN_HAND[Role::Knight as usize]);
+        // White has given two checks, black one
+        let checks = variant_term(Variant::ThreeCheck, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 1+2 0 1");
+        assert_eq!(checks, CHECKS_GIVEN[2] - CHECKS_GIVEN[1]);
+        // The white king is one step from the hill, the black king three
+        let hill = variant_term(Variant::KingOfTheHill, "4k3/8/8/8/8/2K5/8/8 w - - 0 1");
+        assert_eq!(hill, KING_TO_CENTER[1] - KING_TO_CENTER[3]);
+        let race = variant_term(Variant::RacingKings, "8/8/8/8/8/8/k1K5/8 w - - 0 1");
+        assert_eq!(race, S::ZERO);
+        assert!(variant_term(Variant::RacingKings, "8/8/8/8/2K5/8/k7/8 w - - 0 1").mg() > 0);
+    }
+
+    #[test]
+    fn test_variant_outcomes() {
+        let mut table = PawnTable::new(1);
+        // Black king already on the hill
+        let hill = variant_position(Variant::KingOfTheHill, "8/8/8/8/4k3/8/8/K7 w - - 0 1");
+        assert_eq!(calculate_score(&hill, &mut table), Score::Mate(-1));
+        // Antichess: having more material is bad
+        let antichess = variant_position(Variant::Antichess, "4k3/8/8/8/8/8/8/QQ2K3 b - - 0 1");
+        assert!(calculate_score(&antichess, &mut table).is_negative());
+    }
+}
@@ -0,0 +1,134 @@
+use shakmaty::{Color, Move, Role, Square};
+
+// History scores saturate towards this bound
+pub const MAX_HISTORY: i32 = 16_384;
+
+const PIECES: usize = 12;
+
+// The piece that moved and where it went, the key of the follow-up tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PieceTo {
+    piece: usize,
+    to: Square,
+}
+
+impl PieceTo {
+    pub fn new(color: Color, mov: &Move) -> Self {
+        Self { piece: color as usize * 6 + mov.role() as usize - 1, to: mov.to() }
+    }
+
+    fn index(self) -> usize {
+        self.piece * 64 + self.to as usize
+    }
+}
+
+// Bonus for a move that caused a cutoff, deeper cutoffs count for more
+pub fn history_bonus(depth: i16) -> i32 {
+    (16 * depth as i32 * depth as i32).min(1200)
+}
+
+// Moves the entry towards the bonus, the closer it is to the bound the smaller the step
+fn apply_bonus(entry: &mut i16, bonus: i32) {
+    let value = *entry as i32;
+    *entry = (value + bonus - value * bonus.abs() / MAX_HISTORY) as i16;
+}
+
+// The quiet move that last refuted each previous move
+pub struct CounterMoves {
+    moves: Vec<Option<Move>>,
+}
+
+impl CounterMoves {
+    pub fn new() -> Self {
+        Self { moves: vec![None; PIECES * 64] }
+    }
+    pub fn get(&self, prev: PieceTo) -> Option<&Move> {
+        self.moves[prev.index()].as_ref()
+    }
+    pub fn set(&mut self, prev: PieceTo, mov: Move) {
+        self.moves[prev.index()] = Some(mov);
+    }
+}
+
+// How well a move did after a given move earlier in the line, indexed by
+// (previous piece, previous to, piece, to)
+pub struct ContinuationHistory {
+    table: Vec<i16>,
+}
+
+impl ContinuationHistory {
+    pub fn new() -> Self {
+        Self { table: vec![0; PIECES * 64 * PIECES * 64] }
+    }
+    pub fn get(&self, prev: PieceTo, current: PieceTo) -> i32 {
+        self.table[prev.index() * PIECES * 64 + current.index()] as i32
+    }
+    pub fn update(&mut self, prev: PieceTo, current: PieceTo, bonus: i32) {
+        apply_bonus(&mut self.table[prev.index() * PIECES * 64 + current.index()], bonus);
+    }
+}
+
+// How well captures did, indexed by (piece, to, captured role)
+pub struct CaptureHistory {
+    table: Vec<i16>,
+}
+
+impl CaptureHistory {
+    pub fn new() -> Self {
+        Self { table: vec![0; PIECES * 64 * 6] }
+    }
+    fn index(mov: &PieceTo, captured: Role) -> usize {
+        mov.index() * 6 + captured as usize - 1
+    }
+    pub fn get(&self, mov: PieceTo, captured: Role) -> i32 {
+        self.table[Self::index(&mov, captured)] as i32
+    }
+    pub fn update(&mut self, mov: PieceTo, captured: Role, bonus: i32) {
+        apply_bonus(&mut self.table[Self::index(&mov, captured)], bonus);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quiet(role: Role, from: Square, to: Square) -> Move {
+        Move::Normal { role, from, capture: None, to, promotion: None }
+    }
+
+    #[test]
+    fn test_continuation_history_saturates() {
+        let mut history = ContinuationHistory::new();
+        let prev = PieceTo::new(Color::White, &quiet(Role::Knight, Square::G1, Square::F3));
+        let reply = PieceTo::new(Color::Black, &quiet(Role::Knight, Square::B8, Square::C6));
+        let other = PieceTo::new(Color::Black, &quiet(Role::Knight, Square::G8, Square::F6));
+
+        for _ in 0..100 {
+            history.update(prev, reply, history_bonus(20));
+            history.update(prev, other, -history_bonus(20));
+        }
+        assert!(history.get(prev, reply) > 0 && history.get(prev, reply) <= MAX_HISTORY);
+        assert!(history.get(prev, other) < 0 && history.get(prev, other) >= -MAX_HISTORY);
+        // Other follow-ups are untouched
+        assert_eq!(history.get(other, reply), 0);
+    }
+
+    #[test]
+    fn test_counter_moves() {
+        let mut counters = CounterMoves::new();
+        let prev = PieceTo::new(Color::White, &quiet(Role::Bishop, Square::F1, Square::B5));
+        assert!(counters.get(prev).is_none());
+        let reply = quiet(Role::Pawn, Square::A7, Square::A6);
+        counters.set(prev, reply.clone());
+        assert_eq!(counters.get(prev), Some(&reply));
+    }
+
+    #[test]
+    fn test_capture_history_by_victim() {
+        let mut history = CaptureHistory::new();
+        let mov = PieceTo::new(Color::White, &quiet(Role::Bishop, Square::C4, Square::F7));
+        history.update(mov, Role::Pawn, history_bonus(6));
+        assert!(history.get(mov, Role::Pawn) > 0);
+        assert_eq!(history.get(mov, Role::Knight), 0);
+    }
+}
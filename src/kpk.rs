@@ -0,0 +1,227 @@
+use std::sync::OnceLock;
+
+use shakmaty::{attacks, Color, File, Rank, Square};
+
+// King and pawn against king bitbase, built once by retrograde analysis.
+// Positions are stored with the strong side as white and the pawn on files A-D.
+const MAX_INDEX: usize = 2 * 24 * 64 * 64;
+
+#[derive(Clone, Copy, PartialEq)]
+enum KpkResult {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+struct KpkPosition {
+    turn: Color,
+    white_king: Square,
+    black_king: Square,
+    pawn: Square,
+    result: KpkResult,
+}
+
+fn index(turn: Color, black_king: Square, white_king: Square, pawn: Square) -> usize {
+    (turn == Color::Black) as usize
+        | (black_king as usize) << 1
+        | (white_king as usize) << 7
+        | (pawn.file() as usize) << 13
+        | (Rank::Seventh as usize - pawn.rank() as usize) << 15
+}
+
+impl KpkPosition {
+    fn new(idx: usize) -> Self {
+        let turn = if idx & 1 == 0 { Color::White } else { Color::Black };
+        let black_king = Square::new(((idx >> 1) & 0x3f) as u32);
+        let white_king = Square::new(((idx >> 7) & 0x3f) as u32);
+        let file = File::new(((idx >> 13) & 0x3) as u32);
+        let rank = Rank::new(Rank::Seventh as u32 - ((idx >> 15) & 0x7) as u32);
+        let pawn = Square::from_coords(file, rank);
+        let push = pawn.offset(8);
+
+        let white_king_attacks = attacks::king_attacks(white_king);
+        let black_king_attacks = attacks::king_attacks(black_king);
+        let pawn_attacks = attacks::pawn_attacks(Color::White, pawn);
+
+        let result = if white_king.distance(black_king) <= 1
+            || white_king == pawn
+            || black_king == pawn
+            || (turn == Color::White && pawn_attacks.contains(black_king))
+        {
+            // Two pieces on one square, or a king can be captured
+            KpkResult::Invalid
+        } else if turn == Color::White
+            && rank == Rank::Seventh
+            && push != Some(white_king)
+            && push.is_some_and(|sq| black_king.distance(sq) > 1 || white_king_attacks.contains(sq))
+        {
+            // The pawn promotes without being captured
+            KpkResult::Win
+        } else if turn == Color::Black
+            && ((black_king_attacks & !(white_king_attacks | pawn_attacks)).is_empty()
+                || (black_king_attacks & !white_king_attacks).contains(pawn))
+        {
+            // Stalemate, or the black king takes the pawn
+            KpkResult::Draw
+        } else {
+            KpkResult::Unknown
+        };
+
+        Self { turn, white_king, black_king, pawn, result }
+    }
+
+    // White to move wins if any move wins, black to move draws if any move draws
+    fn classify(&self, db: &[KpkPosition]) -> KpkResult {
+        let (good, bad) = match self.turn {
+            Color::White => (KpkResult::Win, KpkResult::Draw),
+            Color::Black => (KpkResult::Draw, KpkResult::Win),
+        };
+
+        let mut successors = Vec::with_capacity(10);
+        match self.turn {
+            Color::White => {
+                for sq in attacks::king_attacks(self.white_king) {
+                    successors.push(index(Color::Black, self.black_king, sq, self.pawn));
+                }
+                if let Some(single) = self.pawn.offset(8)
+                    && self.pawn.rank() < Rank::Seventh
+                {
+                    successors.push(index(Color::Black, self.black_king, self.white_king, single));
+                    if self.pawn.rank() == Rank::Second
+                        && single != self.white_king
+                        && single != self.black_king
+                    {
+                        let double = single.offset(8).unwrap();
+                        successors.push(index(Color::Black, self.black_king, self.white_king, double));
+                    }
+                }
+            }
+            Color::Black => {
+                for sq in attacks::king_attacks(self.black_king) {
+                    successors.push(index(Color::White, sq, self.white_king, self.pawn));
+                }
+            }
+        }
+
+        let results = successors.into_iter().map(|idx| db[idx].result);
+        let mut unknown = false;
+        for result in results {
+            if result == good {
+                return good;
+            }
+            unknown |= result == KpkResult::Unknown;
+        }
+        if unknown { KpkResult::Unknown } else { bad }
+    }
+}
+
+fn bitbase() -> &'static Vec<u64> {
+    static BITBASE: OnceLock<Vec<u64>> = OnceLock::new();
+    BITBASE.get_or_init(|| {
+        let mut db: Vec<KpkPosition> = (0..MAX_INDEX).map(KpkPosition::new).collect();
+
+        // Iterate until no unknown position can be resolved any more
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for idx in 0..MAX_INDEX {
+                if db[idx].result == KpkResult::Unknown {
+                    let result = db[idx].classify(&db);
+                    if result != KpkResult::Unknown {
+                        db[idx].result = result;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let mut wins = vec![0u64; MAX_INDEX / 64];
+        for (idx, pos) in db.iter().enumerate() {
+            if pos.result == KpkResult::Win {
+                wins[idx / 64] |= 1 << (idx % 64);
+            }
+        }
+        wins
+    })
+}
+
+// Builds the bitbase ahead of the first probe
+pub fn init() {
+    bitbase();
+}
+
+// Whether the side with the pawn wins with best play
+pub fn probe(strong: Color, strong_king: Square, pawn: Square, weak_king: Square, turn: Color) -> bool {
+    let normalize = |sq: Square| {
+        let sq = if strong == Color::White { sq } else { sq.flip_vertical() };
+        if pawn.file() >= File::E { sq.flip_horizontal() } else { sq }
+    };
+    let turn = if turn == strong { Color::White } else { Color::Black };
+    let pawn = normalize(pawn);
+    if matches!(pawn.rank(), Rank::First | Rank::Eighth) {
+        return false;
+    }
+    let idx = index(turn, normalize(weak_king), normalize(strong_king), pawn);
+    bitbase()[idx / 64] & (1 << (idx % 64)) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::position;
+    use shakmaty::Position;
+
+    fn probe_fen(fen: &str) -> bool {
+        let pos = position(fen);
+        let board = pos.board();
+        let pawn = board.pawns().first().unwrap();
+        let strong = board.color_at(pawn).unwrap();
+        probe(strong, board.king_of(strong).unwrap(), pawn, board.king_of(!strong).unwrap(), pos.turn())
+    }
+
+    #[test]
+    fn test_opposition() {
+        // The side to move loses the opposition
+        assert!(!probe_fen("8/3k4/8/3K4/3P4/8/8/8 w - - 0 1"));
+        assert!(probe_fen("8/3k4/8/3K4/3P4/8/8/8 b - - 0 1"));
+    }
+
+    #[test]
+    fn test_king_on_sixth_wins() {
+        assert!(probe_fen("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"));
+        assert!(probe_fen("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"));
+    }
+
+    #[test]
+    fn test_rook_pawn_corner_draw() {
+        assert!(!probe_fen("k7/8/8/P1K5/8/8/8/8 w - - 0 1"));
+        assert!(!probe_fen("k7/8/8/P1K5/8/8/8/8 b - - 0 1"));
+    }
+
+    #[test]
+    fn test_pawn_on_seventh() {
+        // Kf6 Kd7 Kf7 wins, while black to move is stalemated
+        assert!(probe_fen("4k3/4P3/4K3/8/8/8/8/8 w - - 0 1"));
+        assert!(!probe_fen("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"));
+    }
+
+    #[test]
+    fn test_rule_of_the_square() {
+        assert!(probe_fen("8/6k1/8/P7/8/8/8/7K b - - 0 1"));
+        assert!(!probe_fen("8/8/8/P2k4/8/8/8/7K b - - 0 1"));
+    }
+
+    #[test]
+    fn test_symmetry() {
+        // Black pawn on the h-file mirrors a white pawn on the a-file
+        assert_eq!(
+            probe_fen("8/3k4/8/3K4/3P4/8/8/8 b - - 0 1"),
+            probe_fen("8/8/8/4p3/4k3/8/4K3/8 w - - 0 1")
+        );
+        assert_eq!(
+            probe_fen("8/3k4/8/3K4/3P4/8/8/8 w - - 0 1"),
+            probe_fen("8/8/8/4p3/4k3/8/4K3/8 b - - 0 1")
+        );
+    }
+}
@@ -0,0 +1,46 @@
+//! Voin, a UCI and XBoard chess engine.
+//!
+//! The [`Engine`] type holds a position and searches or evaluates it; the `voin`
+//! binary is a thin protocol layer on top of it.
+//!
+//! ```
+//! use voin::shakmaty::CastlingMode;
+//! use voin::{Engine, SearchLimits};
+//!
+//! let mut engine = Engine::new();
+//! engine.set_position(Some("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"), &[]).unwrap();
+//! let result = engine.search(&SearchLimits { depth: Some(3), ..Default::default() }, ());
+//! assert_eq!(result.best_move.unwrap().to_uci(CastlingMode::Standard).to_string(), "a1a8");
+//! ```
+
+mod search;
+mod evaluation;
+mod utils;
+mod score;
+mod transposition;
+mod pawn_table;
+mod history;
+mod see;
+mod skill;
+mod movepick;
+mod endgame;
+mod kpk;
+mod tablebase;
+mod variant;
+mod engine;
+pub mod bench;
+pub mod book;
+pub mod pgn;
+pub mod makebook;
+pub mod protocol;
+pub mod xboard;
+
+pub use engine::{Engine, EngineError, SearchHandle};
+pub use evaluation::{calculate_score, evaluation_breakdown, get_piece_value};
+pub use pawn_table::PawnTable;
+pub use score::{Score, S, MAX_PHASE};
+pub use search::{Bound, SearchInfo, SearchLimits, SearchObserver, SearchOptions, SearchResult};
+pub use skill::MAX_LEVEL;
+pub use utils::Prng;
+pub use variant::EnginePosition;
+pub use shakmaty;
@@ -1,58 +1,244 @@
-mod search;
-mod evaluation;
-mod utils;
-mod score;
-mod transposition;
-
-use std::{io};
-use shakmaty::{Chess, fen::Fen, CastlingMode, Position, uci::UciMove, Color};
-use search::*;
+use std::io;
+use std::sync::mpsc;
+use std::time::Duration;
+use voin::shakmaty::{CastlingMode, Position, Color};
+use voin::shakmaty::variant::Variant;
+use voin::book::Book;
+use voin::protocol::{self, BackgroundSearch, Event};
+use voin::{bench, makebook, xboard, Bound, Engine, EnginePosition, Prng, SearchInfo, SearchLimits, MAX_LEVEL, MAX_PHASE};
 
 fn print_engine_info() {
     println!("id name Voin");
     println!("id author Kuznetsov Makar");
+    print!("option name UCI_Variant type combo default chess");
+    for variant in Variant::ALL {
+        print!(" var {}", variant.uci());
+    }
+    println!();
+    println!("option name OwnBook type check default false");
+    println!("option name BookFile type string default <empty>");
+    println!("option name BookDepth type spin default 20 min 1 max 100");
+    println!("option name BookVariety type check default false");
+    println!("option name Ponder type check default false");
+    println!("option name IIR type check default true");
+    println!("option name IID type check default false");
+    println!("option name ProbCut type check default true");
+    println!("option name ProbCutMargin type spin default 200 min 0 max 1000");
+    println!("option name ProbCutDepth type spin default 5 min 2 max 20");
+    println!("option name Contempt type spin default 0 min -100 max 100");
+    println!("option name UCI_AnalyseMode type check default false");
+    println!("option name Skill Level type spin default {} min 0 max {}", MAX_LEVEL, MAX_LEVEL);
+    println!("option name HashFile type string default voin.hash");
+    println!("option name SaveHash type button");
+    println!("option name LoadHash type button");
     println!("uciok");
 }
 
+fn save_hash(engine: &Engine, path: &str) {
+    match engine.save_hash(path) {
+        Ok(()) => println!("info string saved hash to {}", path),
+        Err(err) => println!("info string cannot save hash to {}: {}", path, err),
+    }
+}
+
+fn load_hash(engine: &mut Engine, path: &str) {
+    match engine.load_hash(path) {
+        Ok(()) => println!("info string loaded hash from {}", path),
+        Err(err) => println!("info string cannot load hash from {}: {}", path, err),
+    }
+}
+
+fn print_search_info(info: &SearchInfo) {
+    match info {
+        SearchInfo::Iteration { depth, seldepth, score, bound, nodes, time, tb_hits, hashfull, pv } => {
+            let bound = match bound {
+                Bound::Exact => "",
+                Bound::Lower => " lowerbound",
+                Bound::Upper => " upperbound",
+            };
+            let pv: Vec<String> = pv.iter().map(|m| m.to_uci(CastlingMode::Standard).to_string()).collect();
+            println!(
+                "info depth {} seldepth {} {}{} nodes {} time {} hashfull {} tbhits {} pv {}",
+                depth,
+                seldepth,
+                score,
+                bound,
+                nodes,
+                time.as_millis(),
+                hashfull,
+                tb_hits,
+                pv.join(" ")
+            );
+        }
+        SearchInfo::CurrMove { depth, mov, number } => println!(
+            "info depth {} currmove {} currmovenumber {}",
+            depth,
+            mov.to_uci(CastlingMode::Standard),
+            number
+        ),
+        SearchInfo::Message(message) => println!("info string {}", message),
+    }
+}
+
 fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "makebook") {
+        return makebook::run(&args[2..]);
+    }
+    if args.get(1).is_some_and(|arg| arg == "bench") {
+        let depth = args.get(2).and_then(|depth| depth.parse().ok()).unwrap_or(bench::DEFAULT_DEPTH);
+        bench::run(&Engine::new(), depth);
+        return Ok(());
+    }
+
+    let (tx, events) = mpsc::channel();
+    protocol::spawn_input(tx.clone());
+
+    // The protocol is chosen by the first command
+    let mut pending = None;
+    for event in events.iter() {
+        if let Event::Line(line) = event
+            && !line.trim().is_empty()
+        {
+            if line.trim() == "xboard" {
+                xboard::run(Engine::new(), events, tx);
+                return Ok(());
+            }
+            pending = Some(line);
+            break;
+        }
+    }
+
     let mut enabled = true;
-    let mut pos = Chess::default();
+    let mut engine = Engine::new();
+    let mut search: Option<BackgroundSearch> = None;
+    let mut search_id = 0;
+    let mut book: Option<Book> = None;
+    let mut own_book = false;
+    let mut book_depth = 20;
+    let mut book_variety = false;
+    let mut rng = Prng::from_time();
+    let mut hash_file = "voin.hash".to_string();
     while enabled {
-        let mut line = String::new();
-        io::stdin().read_line(&mut line)?;
+        let line = match pending.take() {
+            Some(line) => line,
+            None => match events.recv() {
+                Ok(Event::Line(line)) => line,
+                Ok(Event::SearchDone(id, result)) => {
+                    if search.as_ref().is_some_and(|search| search.id == id) {
+                        search = None;
+                        // A null move when there is nothing to play
+                        let best_move = result.best_move.map_or("0000".to_string(), |m| m.to_uci(CastlingMode::Standard).to_string());
+                        let mut output = format!("bestmove {}", best_move);
+                        if let Some(ponder) = result.pv.get(1) {
+                            output += &format!(" ponder {}", ponder.to_uci(CastlingMode::Standard));
+                        }
+                        println!("{}", output);
+                    }
+                    continue;
+                }
+                Err(_) => break,
+            },
+        };
         let tokens: Vec<&str> = line.split_whitespace().collect();
-        if tokens.len() > 0 {
+        if !tokens.is_empty() {
             let cmd: String = tokens[0].to_string();
             match cmd.as_str() {
                 "uci" => print_engine_info(),
-                "isready" => println!("readyok"),
+                "isready" => {
+                    engine.init_tables();
+                    println!("readyok");
+                }
+                "setoption" => {
+                    // setoption name <id> value <x>
+                    let value_idx = tokens.iter().position(|&x| x == "value");
+                    let name = tokens[2..value_idx.unwrap_or(tokens.len())].join(" ");
+                    let value = value_idx.map(|idx| tokens[idx + 1..].join(" ")).unwrap_or_default();
+                    match name.as_str() {
+                        "UCI_Variant" => match Variant::from_uci(&value) {
+                            Ok(variant) => engine.set_variant(variant),
+                            Err(_) => println!("info string unknown variant {}", value),
+                        },
+                        "OwnBook" => own_book = value == "true",
+                        "BookFile" => {
+                            book = match Book::open(&value) {
+                                Ok(book) => Some(book),
+                                Err(err) => {
+                                    println!("info string cannot open book {}: {}", value, err);
+                                    None
+                                }
+                            };
+                        }
+                        "BookDepth" => book_depth = value.parse().unwrap_or(book_depth),
+                        "BookVariety" => book_variety = value == "true",
+                        "IIR" => engine.options_mut().iir = value == "true",
+                        "IID" => engine.options_mut().iid = value == "true",
+                        "ProbCut" => engine.options_mut().probcut = value == "true",
+                        "ProbCutMargin" => {
+                            let options = engine.options_mut();
+                            options.probcut_margin = value.parse().unwrap_or(options.probcut_margin);
+                        }
+                        "ProbCutDepth" => {
+                            let options = engine.options_mut();
+                            options.probcut_depth = value.parse().unwrap_or(options.probcut_depth);
+                        }
+                        "Contempt" => {
+                            let options = engine.options_mut();
+                            options.contempt = value.parse().unwrap_or(options.contempt);
+                        }
+                        "UCI_AnalyseMode" => engine.options_mut().analyse_mode = value == "true",
+                        "Skill Level" => {
+                            let options = engine.options_mut();
+                            options.skill_level = value.parse().unwrap_or(options.skill_level);
+                        }
+                        "HashFile" => hash_file = value,
+                        "SaveHash" => save_hash(&engine, &hash_file),
+                        "LoadHash" => load_hash(&mut engine, &hash_file),
+                        _ => {}
+                    }
+                }
+                "debug" => engine.options_mut().debug = tokens.get(1) == Some(&"on"),
                 "ucinewgame" => {
-                    pos = Chess::default();
+                    if let Some(search) = &search {
+                        search.stop();
+                    }
+                    engine.set_variant(engine.variant());
+                    engine.clear_hash();
                 }
-                "position" => {
-                    let board_type = tokens[1];
-                    if board_type == "startpos" {
-                        pos = Chess::default();
+                "savehash" | "loadhash" => {
+                    // savehash|loadhash [<file>], by default the HashFile option
+                    let path = if tokens.len() > 1 { tokens[1..].join(" ") } else { hash_file.clone() };
+                    if cmd == "savehash" {
+                        save_hash(&engine, &path);
                     } else {
-                        let fen: Fen;
-                        if tokens.contains(&"moves") {
-                            let idx: usize = tokens.iter().position(|&x| x == "moves").unwrap();
-                            fen = tokens[2..idx].join(" ").parse().unwrap();
-                        } else {
-                            fen = tokens[2..].join(" ").parse().unwrap();
-                        }
-                        pos = fen.into_position(CastlingMode::Standard).unwrap();
+                        load_hash(&mut engine, &path);
                     }
-                    if tokens.contains(&"moves") {
-                        let idx: usize = tokens.iter().position(|&x| x == "moves").unwrap() + 1;
-                        for s in tokens[idx..].iter() {
-                            let uci: UciMove = s.parse().unwrap();
-                            let m = uci.to_move(&pos).unwrap();
-                            pos.play_unchecked(&m);
-                        }
+                }
+                "position" => {
+                    // position startpos|fen <fen> [moves <move>...]
+                    let moves_idx = tokens.iter().position(|&x| x == "moves").unwrap_or(tokens.len());
+                    let fen = (tokens.get(1) == Some(&"fen")).then(|| tokens[2..moves_idx].join(" "));
+                    let moves = tokens.get(moves_idx + 1..).unwrap_or_default();
+                    if let Err(err) = engine.set_position(fen.as_deref(), moves) {
+                        println!("info string {}", err);
                     }
                 }
                 "go" => {
+                    let pondering = tokens.contains(&"ponder");
+                    let infinite = tokens.contains(&"infinite");
+                    let pos = engine.position();
+                    // Book moves are played at once, up to BookDepth full moves
+                    if own_book
+                        && !pondering
+                        && !infinite
+                        && pos.fullmoves().get() <= book_depth
+                        && let Some(book) = &book
+                        && let Some(chess) = pos.as_chess()
+                        && let Some(book_move) = book.probe(chess, book_variety, &mut rng)
+                    {
+                        println!("bestmove {}", book_move.to_uci(CastlingMode::Standard));
+                        continue;
+                    }
                     let remaining_time: i32;
                     if tokens.contains(&"movetime") {
                         remaining_time = tokens[tokens.iter().position(|&r| r == "movetime").unwrap() + 1].parse().unwrap();
@@ -66,11 +252,51 @@ fn main() -> io::Result<()> {
                     } else {
                         remaining_time = 10_000;
                     }
-                    let (best_move, _best_score) = find_best_move(&pos, remaining_time);
-                    let best_move_uci = best_move.to_uci(CastlingMode::Standard);
-                    println!("bestmove {}", best_move_uci);
+                    let depth = tokens
+                        .iter()
+                        .position(|&r| r == "depth")
+                        .and_then(|idx| tokens.get(idx + 1)?.parse().ok());
+                    let nodes = tokens
+                        .iter()
+                        .position(|&r| r == "nodes")
+                        .and_then(|idx| tokens.get(idx + 1)?.parse().ok());
+                    // A ponder search runs until ponderhit, then the time limit applies
+                    let limits = SearchLimits {
+                        time: (!infinite).then(|| Duration::from_millis(remaining_time as u64 / 40)),
+                        depth,
+                        infinite,
+                        ponder: pondering,
+                        nodes,
+                    };
+                    search_id += 1;
+                    search = Some(BackgroundSearch::start(search_id, &engine, limits, print_search_info, tx.clone()));
+                }
+                "stop" => {
+                    if let Some(search) = &search {
+                        search.stop();
+                    }
+                }
+                "ponderhit" => {
+                    if let Some(search) = &search {
+                        search.ponderhit();
+                    }
+                }
+                "bench" => {
+                    let depth = tokens.get(1).and_then(|depth| depth.parse().ok()).unwrap_or(bench::DEFAULT_DEPTH);
+                    bench::run(&engine, depth);
+                }
+                "eval" => {
+                    let (terms, phase) = engine.evaluation_breakdown();
+                    println!("{:<20}{:>8}{:>8}", "Term", "MG", "EG");
+                    for (term, score) in terms {
+                        println!("{:<20}{:>8}{:>8}", term, score.mg(), score.eg());
+                    }
+                    println!("Phase {}/{}", phase, MAX_PHASE);
                 }
                 "quit" => {
+                    if let Some(search) = &search {
+                        search.stop();
+                    }
                     enabled = false;
                 }
                 &_ => {},
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+use shakmaty::fen::Fen;
+use shakmaty::san::San;
+use shakmaty::{CastlingMode, Chess, Color, Position};
+
+use crate::book::{encode_move, polyglot_key, Book, BookEntry};
+use crate::pgn::{parse_games, Game};
+
+pub struct MakeBookOptions {
+    pub min_elo: u32,
+    pub min_games: u32,
+    pub max_ply: usize,
+}
+
+impl Default for MakeBookOptions {
+    fn default() -> Self {
+        Self { min_elo: 0, min_games: 1, max_ply: 30 }
+    }
+}
+
+#[derive(Default)]
+struct MoveStats {
+    games: u32,
+    // Two points per win and one per draw, for the side playing the move
+    points: u64,
+}
+
+fn passes_elo(game: &Game, min_elo: u32) -> bool {
+    min_elo == 0
+        || ["WhiteElo", "BlackElo"].iter().all(|tag| {
+            game.header(tag)
+                .and_then(|elo| elo.parse::<u32>().ok())
+                .is_some_and(|elo| elo >= min_elo)
+        })
+}
+
+fn winner(game: &Game) -> Option<Option<Color>> {
+    match game.header("Result")? {
+        "1-0" => Some(Some(Color::White)),
+        "0-1" => Some(Some(Color::Black)),
+        "1/2-1/2" => Some(None),
+        _ => None,
+    }
+}
+
+// The position the game starts from, which is given by the FEN tag for games that
+// do not start from the initial position
+fn start_position(game: &Game) -> Option<Chess> {
+    match game.header("FEN") {
+        Some(fen) if game.header("SetUp") != Some("0") => {
+            fen.parse::<Fen>().ok()?.into_position(CastlingMode::Standard).ok()
+        }
+        _ => Some(Chess::default()),
+    }
+}
+
+// Collects per-position move statistics from the games and turns them into a book
+pub fn make_book(games: &[Game], options: &MakeBookOptions) -> Book {
+    let mut stats: HashMap<(u64, u16), MoveStats> = HashMap::new();
+
+    for game in games {
+        let Some(winner) = winner(game) else { continue };
+        if !passes_elo(game, options.min_elo) {
+            continue;
+        }
+
+        let Some(mut pos) = start_position(game) else { continue };
+        for san in game.moves.iter().take(options.max_ply) {
+            let san = san.trim_end_matches(['!', '?']);
+            let Some(m) = San::from_ascii(san.as_bytes()).ok().and_then(|san| san.to_move(&pos).ok()) else {
+                break;
+            };
+            let entry = stats.entry((polyglot_key(&pos), encode_move(&m))).or_default();
+            entry.games += 1;
+            entry.points += match winner {
+                Some(color) if color == pos.turn() => 2,
+                Some(_) => 0,
+                None => 1,
+            };
+            pos.play_unchecked(&m);
+        }
+    }
+
+    // Weights must fit in 16 bits
+    let max_points = stats.values().map(|s| s.points).max().unwrap_or(0);
+    let scale = max_points.div_ceil(u16::MAX as u64).max(1);
+    let entries = stats
+        .into_iter()
+        .filter(|(_, s)| s.games >= options.min_games && s.points > 0)
+        .map(|((key, raw_move), s)| BookEntry {
+            key,
+            raw_move,
+            weight: (s.points / scale).max(1) as u16,
+            learn: 0,
+        })
+        .collect();
+    Book::from_entries(entries)
+}
+
+// makebook <output.bin> <games.pgn>... [--min-elo N] [--min-games N] [--max-ply N]
+pub fn run(args: &[String]) -> io::Result<()> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_string());
+    let mut options = MakeBookOptions::default();
+    let mut files = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || -> io::Result<u32> {
+            args.next()
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| invalid(&format!("{} needs a number", arg)))
+        };
+        match arg.as_str() {
+            "--min-elo" => options.min_elo = value()?,
+            "--min-games" => options.min_games = value()?,
+            "--max-ply" => options.max_ply = value()? as usize,
+            _ => files.push(arg),
+        }
+    }
+    let Some((output, pgn_files)) = files.split_first() else {
+        return Err(invalid("usage: makebook <output.bin> <games.pgn>... [--min-elo N] [--min-games N] [--max-ply N]"));
+    };
+
+    let mut games = Vec::new();
+    for file in pgn_files {
+        games.extend(parse_games(&fs::read_to_string(file)?));
+    }
+    let book = make_book(&games, &options);
+    if book.is_empty() {
+        return Err(invalid("no book entries left after filtering"));
+    }
+    book.save(output)?;
+    println!("{} games, {} book entries written to {}", games.len(), book.len(), output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{position, Prng};
+
+    const PGN: &str = r#"[White "A"]
+[Black "B"]
+[WhiteElo "2400"]
+[BlackElo "2300"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 1-0
+
+[White "C"]
+[Black "D"]
+[WhiteElo "2500"]
+[BlackElo "2450"]
+[Result "1/2-1/2"]
+
+1. e4 c5 2. Nf3 d6 1/2-1/2
+
+[White "E"]
+[Black "F"]
+[WhiteElo "1500"]
+[BlackElo "1400"]
+[Result "0-1"]
+
+1. d4 d5 2. c4 0-1
+"#;
+
+    fn uci(moves: Vec<(shakmaty::Move, u16)>) -> Vec<(String, u16)> {
+        moves.into_iter().map(|(m, w)| (m.to_uci(CastlingMode::Standard).to_string(), w)).collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let games = parse_games(PGN);
+        let book = make_book(&games, &MakeBookOptions::default());
+        let path = std::env::temp_dir().join(format!("voin-makebook-{}.bin", std::process::id()));
+        book.save(&path).unwrap();
+        let book = Book::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let start = Chess::default();
+        // e4 scored a win and a draw, d4 lost its only game
+        assert_eq!(uci(book.moves(&start)), [("e2e4".to_string(), 3)]);
+        let mut rng = Prng::new(1);
+        let e4 = book.probe(&start, false, &mut rng).unwrap();
+        let mut pos = start.clone();
+        pos.play_unchecked(&e4);
+        let mut replies = uci(book.moves(&pos));
+        replies.sort();
+        assert_eq!(replies, [("c7c5".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_filters() {
+        let games = parse_games(PGN);
+        let start = Chess::default();
+
+        let book = make_book(&games, &MakeBookOptions { min_games: 2, ..Default::default() });
+        assert_eq!(uci(book.moves(&start)), [("e2e4".to_string(), 3)]);
+        let mut pos = start.clone();
+        pos.play_unchecked(&book.moves(&start)[0].0);
+        assert!(book.moves(&pos).is_empty());
+
+        let book = make_book(&games, &MakeBookOptions { min_elo: 2420, ..Default::default() });
+        assert_eq!(uci(book.moves(&start)), [("e2e4".to_string(), 1)]);
+
+        let book = make_book(&games, &MakeBookOptions { max_ply: 1, ..Default::default() });
+        pos = start.clone();
+        pos.play_unchecked(&book.moves(&start)[0].0);
+        assert!(book.moves(&pos).is_empty());
+    }
+
+    #[test]
+    fn test_games_from_fen() {
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+        let pgn = format!(
+            "[SetUp \"1\"]\n[FEN \"{}\"]\n[Result \"1-0\"]\n\n2. Nf3 Nc6 1-0\n\n[FEN \"not a fen\"]\n[Result \"1-0\"]\n\n1. d4 1-0\n",
+            fen
+        );
+        let book = make_book(&parse_games(&pgn), &MakeBookOptions::default());
+        // Only the moves after the given position, and nothing from the broken FEN
+        assert!(book.moves(&Chess::default()).is_empty());
+        let pos = position(fen);
+        assert_eq!(uci(book.moves(&pos)), [("g1f3".to_string(), 2)]);
+        assert_eq!(book.len(), 1);
+    }
+}
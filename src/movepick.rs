@@ -0,0 +1,72 @@
+use shakmaty::{Board, Move, MoveList};
+
+use crate::see::see_ge;
+
+// Hands out moves best first: the TT move, then by the given ordering score. Moves are
+// picked one at a time, so nodes that cut off early do not pay for sorting everything.
+pub struct MovePicker {
+    moves: Vec<(Move, i32)>,
+}
+
+impl MovePicker {
+    pub fn new(moves: MoveList, tt_move: Option<&Move>, mut order: impl FnMut(&Move) -> i32) -> Self {
+        let moves = moves
+            .into_iter()
+            .map(|m| {
+                let score = if Some(&m) == tt_move { i32::MAX } else { order(&m) };
+                (m, score)
+            })
+            .collect();
+        Self { moves }
+    }
+
+    // ProbCut candidates: captures that win at least the threshold by static exchange
+    pub fn probcut(
+        mut moves: MoveList,
+        board: &Board,
+        tt_move: Option<&Move>,
+        threshold: i32,
+        order: impl FnMut(&Move) -> i32,
+    ) -> Self {
+        moves.retain(|m| m.is_capture() && see_ge(board, m, threshold));
+        Self::new(moves, tt_move, order)
+    }
+}
+
+impl Iterator for MovePicker {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        let best = self.moves.iter().enumerate().max_by_key(|(i, (_, score))| (*score, std::cmp::Reverse(*i)))?.0;
+        Some(self.moves.swap_remove(best).0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::position;
+    use shakmaty::{CastlingMode, Position};
+
+    fn uci(m: Move) -> String {
+        m.to_uci(CastlingMode::Standard).to_string()
+    }
+
+    #[test]
+    fn test_tt_move_then_by_score() {
+        let pos = position("4k3/8/8/3p4/8/8/8/R2QK3 w - - 0 1");
+        let tt_move = pos.legal_moves().into_iter().find(|m| uci(m.clone()) == "a1a8").unwrap();
+        let picker = MovePicker::new(pos.legal_moves(), Some(&tt_move), |m| if m.is_capture() { 100 } else { 0 });
+        let moves: Vec<String> = picker.map(uci).collect();
+        assert_eq!(moves.len(), pos.legal_moves().len());
+        assert_eq!(&moves[..2], ["a1a8", "d1d5"]);
+    }
+
+    #[test]
+    fn test_probcut_keeps_winning_captures() {
+        // Qxd5 loses the queen to the pawn on e6, Rxa7 wins a free pawn
+        let pos = position("4k3/p7/4p3/3p4/8/8/8/R2QK3 w - - 0 1");
+        let picker = MovePicker::probcut(pos.legal_moves(), pos.board(), None, 0, |_| 0);
+        assert_eq!(picker.map(uci).collect::<Vec<_>>(), ["a1a7"]);
+    }
+}
@@ -0,0 +1,85 @@
+use shakmaty::zobrist::{Zobrist64, ZobristValue};
+use shakmaty::{Bitboard, Board, ByColor};
+
+use crate::score::S;
+
+// Everything here depends on pawns only, so it can be shared by all positions
+// with the same pawn structure
+#[derive(Clone, Copy, Default)]
+pub struct PawnEntry {
+    pub key: u64,
+    pub score: S,
+    pub passed: ByColor<Bitboard>,
+    pub attacks: ByColor<Bitboard>,
+    pub attack_spans: ByColor<Bitboard>,
+    pub open_files: Bitboard,
+    pub semi_open_files: ByColor<Bitboard>,
+}
+
+pub struct PawnTable {
+    entries: Vec<Option<PawnEntry>>,
+    size: usize,
+    probes: u64,
+    hits: u64,
+}
+
+impl PawnTable {
+    pub fn new(size: usize) -> Self {
+        Self {
+            entries: vec![None; size],
+            size,
+            probes: 0,
+            hits: 0,
+        }
+    }
+    pub fn get(&mut self, key: u64) -> Option<&PawnEntry> {
+        let index = key as usize % self.size;
+        self.probes += 1;
+        let entry = self.entries[index].as_ref().filter(|entry| entry.key == key);
+        if entry.is_some() {
+            self.hits += 1;
+        }
+        entry
+    }
+    pub fn insert(&mut self, entry: PawnEntry) {
+        let index = entry.key as usize % self.size;
+        self.entries[index] = Some(entry);
+    }
+    pub fn hit_rate(&self) -> f64 {
+        if self.probes == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.probes as f64
+        }
+    }
+}
+
+// Zobrist key built from the pawns alone
+pub fn pawn_key(board: &Board) -> u64 {
+    let mut key = Zobrist64::default();
+    for sq in board.pawns() {
+        key ^= Zobrist64::zobrist_for_piece(sq, board.piece_at(sq).unwrap());
+    }
+    key.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::{Chess, Position};
+
+    use crate::evaluation::calculate_score;
+
+    #[test]
+    fn test_hit_rate() {
+        let mut table = PawnTable::new(1024);
+        assert_eq!(table.hit_rate(), 0.0);
+        // The pawn structure is cached by the first evaluation and found by the next ones
+        let pos = Chess::default();
+        for _ in 0..4 {
+            calculate_score(&pos, &mut table);
+        }
+        assert_eq!(table.hit_rate(), 0.75);
+        assert!(table.get(pawn_key(pos.board())).is_some());
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+// One game from a PGN file: its tag pairs and the main line in SAN
+#[derive(Default)]
+pub struct Game {
+    pub headers: HashMap<String, String>,
+    pub moves: Vec<String>,
+}
+
+impl Game {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+fn is_result(token: &str) -> bool {
+    matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*")
+}
+
+// Splits PGN text into games. Comments, variations, NAGs and move numbers are skipped.
+pub fn parse_games(text: &str) -> Vec<Game> {
+    let mut games = Vec::new();
+    let mut game = Game::default();
+    let mut in_movetext = false;
+    let mut depth: u32 = 0; // nesting of comments and variations
+
+    for line in text.lines() {
+        let line = line.trim();
+        if depth == 0 && line.starts_with('[') {
+            // A tag after movetext starts a new game without a result token
+            if in_movetext {
+                games.push(std::mem::take(&mut game));
+                in_movetext = false;
+            }
+            if let Some((name, value)) = line.trim_matches(|c| c == '[' || c == ']').split_once(' ') {
+                game.headers.insert(name.to_string(), value.trim().trim_matches('"').to_string());
+            }
+            continue;
+        }
+        if line.starts_with('%') {
+            continue;
+        }
+
+        let mut token = String::new();
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' | '(' => depth += 1,
+                // A stray closing brace or parenthesis is ignored
+                '}' | ')' => depth = depth.saturating_sub(1),
+                ';' if depth == 0 => break,
+                _ if depth > 0 => {}
+                c if c.is_whitespace() => {}
+                _ => {
+                    token.push(c);
+                    if chars.peek().is_some_and(|&next| !next.is_whitespace() && !"{}();".contains(next)) {
+                        continue;
+                    }
+                }
+            }
+            if token.is_empty() {
+                continue;
+            }
+            in_movetext = true;
+            // Strip move numbers such as "12." or "12..." glued to the move
+            let san = token.rsplit('.').next().unwrap();
+            if is_result(&token) {
+                game.headers.entry("Result".to_string()).or_insert(token.clone());
+                games.push(std::mem::take(&mut game));
+                in_movetext = false;
+            } else if !san.is_empty() && !san.starts_with('$') {
+                game.moves.push(san.to_string());
+            }
+            token.clear();
+        }
+    }
+    if in_movetext {
+        games.push(game);
+    }
+    games
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_games() {
+        let text = r#"[Event "Test"]
+[White "A"]
+[Result "1-0"]
+[WhiteElo "2100"]
+
+1. e4 {best by test} e5 2. Nf3 (2. f4 exf4) Nc6 3.Bb5 $1 a6 ; Ruy Lopez
+4. Ba4 1-0
+
+[Event "Second"]
+[Result "1/2-1/2"]
+
+1. d4 d5 2. c4 e6 1/2-1/2
+"#;
+        let games = parse_games(text);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].header("White"), Some("A"));
+        assert_eq!(games[0].header("WhiteElo"), Some("2100"));
+        assert_eq!(games[0].moves, ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4"]);
+        assert_eq!(games[1].header("Result"), Some("1/2-1/2"));
+        assert_eq!(games[1].moves, ["d4", "d5", "c4", "e6"]);
+    }
+
+    #[test]
+    fn test_unmatched_closing() {
+        let games = parse_games("1. e4 e5 } 2. Nf3 ) Nc6 {comment} 3. Bb5 1-0\n");
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].moves, ["e4", "e5", "Nf3", "Nc6", "Bb5"]);
+    }
+}
@@ -0,0 +1,67 @@
+use std::io;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use crate::engine::{Engine, SearchHandle};
+use crate::search::{SearchLimits, SearchObserver, SearchResult};
+
+// Input lines and finished searches, merged so a front end can react to both
+pub enum Event {
+    Line(String),
+    SearchDone(u64, SearchResult),
+}
+
+// Forwards stdin line by line, ending with "quit" when the input is closed
+pub fn spawn_input(tx: Sender<Event>) {
+    thread::spawn(move || {
+        loop {
+            let mut line = String::new();
+            match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    let _ = tx.send(Event::Line("quit".to_string()));
+                    break;
+                }
+                Ok(_) => {
+                    if tx.send(Event::Line(line)).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+// A search running on its own thread. Its result comes back as Event::SearchDone
+// tagged with the id, so results of searches that were given up can be told apart.
+pub struct BackgroundSearch {
+    pub id: u64,
+    handle: SearchHandle,
+}
+
+impl BackgroundSearch {
+    // Searches the engine's current position, the observer prints the progress
+    pub fn start(
+        id: u64,
+        engine: &Engine,
+        limits: SearchLimits,
+        observer: impl SearchObserver + Send + 'static,
+        tx: Sender<Event>,
+    ) -> Self {
+        let handle = engine.start_search(
+            limits,
+            observer,
+            move |result| {
+                let _ = tx.send(Event::SearchDone(id, result));
+            },
+        );
+        Self { id, handle }
+    }
+
+    pub fn stop(&self) {
+        self.handle.stop();
+    }
+
+    pub fn ponderhit(&self) {
+        self.handle.ponderhit();
+    }
+}
@@ -5,6 +5,13 @@ use shakmaty::Color;
 
 use crate::utils::signum;
 
+// Tablebase scores lie within this many plies of Score::TB_WIN
+const TB_MAX_PLY: i16 = 256;
+
+fn is_tb_score(val: i16) -> bool {
+    (Score::TB_WIN - TB_MAX_PLY..=Score::TB_WIN).contains(&val.saturating_abs())
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Score {
     Centipawn(i16),
@@ -15,6 +22,38 @@ impl Score {
     pub const MAX: Self = Score::Centipawn(i16::MAX);
     pub const MIN: Self = Score::Centipawn(i16::MIN);
     pub const ZERO: Self = Score::Centipawn(0);
+    // Tablebase wins sit above any evaluation, shortened by the ply they are found at
+    pub const TB_WIN: i16 = 20_000;
+
+    #[inline]
+    pub fn tb_win(ply: i16) -> Self {
+        Score::Centipawn(Self::TB_WIN - ply)
+    }
+
+    #[inline]
+    pub fn tb_loss(ply: i16) -> Self {
+        -Self::tb_win(ply)
+    }
+
+    // Tablebase scores count plies from the root, while the transposition table keeps them
+    // from the node they are stored at, as mate scores are
+    pub fn to_tt(self, ply: i16) -> Self {
+        match self {
+            Self::Centipawn(val) if is_tb_score(val) => {
+                Self::Centipawn(val + ply * val.signum())
+            }
+            _ => self,
+        }
+    }
+
+    pub fn from_tt(self, ply: i16) -> Self {
+        match self {
+            Self::Centipawn(val) if is_tb_score(val) => {
+                Self::Centipawn(val - ply * val.signum())
+            }
+            _ => self,
+        }
+    }
 
     #[inline]
     pub fn apply_color_factor(self, color: Color) -> Self {
@@ -42,6 +81,15 @@ impl Score {
             Self::Mate(val) => val.is_negative(),
         }
     }
+    // Centipawns, or 100000 plus the moves to mate as xboard expects
+    pub fn to_xboard(self) -> i32 {
+        match self {
+            Self::Centipawn(val) => val as i32,
+            Self::Mate(val) if val > 0 => 100_000 + mate_moves(val) as i32,
+            Self::Mate(val) => -100_000 + mate_moves(val) as i32,
+        }
+    }
+
     pub fn increment_mate_depth(&self) -> Self {
         match self {
             Self::Mate(val) => Self::Mate(val + signum(*val as isize)),
@@ -56,11 +104,16 @@ impl std::fmt::Display for Score {
             _ if self.is_max() => write!(f, "score upperbound"),
             _ if self.is_min() => write!(f, "score lowerbound"),
             Self::Centipawn(val) => write!(f, "score cp {}", val),
-            Self::Mate(val) => write!(f, "score mate {}", (val - signum(*val as isize)) / 2 + (val - signum(*val as isize)) % 2)
+            Self::Mate(val) => write!(f, "score mate {}", mate_moves(*val))
         }
     }
 }
 
+// Full moves until mate, negative when being mated
+fn mate_moves(val: i8) -> i8 {
+    (val - signum(val as isize)) / 2 + (val - signum(val as isize)) % 2
+}
+
 impl ops::Neg for Score {
     type Output = Self;
 
@@ -175,6 +228,102 @@ impl ops::Mul<i16> for Score {
     }
 }
 
+// Middlegame and endgame values packed into one integer, so that evaluation terms
+// can be summed with a single addition and interpolated once at the end
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct S {
+    packed: i32,
+}
+
+#[allow(non_snake_case)]
+#[inline]
+pub const fn S(mg: i16, eg: i16) -> S {
+    S { packed: ((eg as i32) << 16).wrapping_add(mg as i32) }
+}
+
+impl S {
+    pub const ZERO: Self = S(0, 0);
+
+    #[inline]
+    pub const fn mg(self) -> i16 {
+        self.packed as i16
+    }
+
+    #[inline]
+    pub const fn eg(self) -> i16 {
+        (self.packed.wrapping_add(0x8000) >> 16) as i16
+    }
+
+    // Interpolates between mg and eg by a game phase from 0 (endgame) to MAX_PHASE (opening)
+    #[inline]
+    pub fn taper(self, phase: i32) -> Score {
+        let mg = self.mg() as i32;
+        let eg = self.eg() as i32;
+        Score::Centipawn(((mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE) as i16)
+    }
+
+    #[inline]
+    pub fn apply_color_factor(self, color: Color) -> Self {
+        if matches!(color, Color::Black) {
+            -self
+        } else {
+            self
+        }
+    }
+}
+
+pub const MAX_PHASE: i32 = 24;
+
+impl ops::Add for S {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, rhs: Self) -> Self::Output {
+        S { packed: self.packed.wrapping_add(rhs.packed) }
+    }
+}
+
+impl ops::Sub for S {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, rhs: Self) -> Self::Output {
+        S { packed: self.packed.wrapping_sub(rhs.packed) }
+    }
+}
+
+impl ops::Neg for S {
+    type Output = Self;
+
+    #[inline]
+    fn neg(self) -> Self::Output {
+        S { packed: self.packed.wrapping_neg() }
+    }
+}
+
+impl ops::Mul<i32> for S {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, rhs: i32) -> Self::Output {
+        S { packed: self.packed.wrapping_mul(rhs) }
+    }
+}
+
+impl ops::AddAssign for S {
+    #[inline]
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl ops::SubAssign for S {
+    #[inline]
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -226,6 +375,26 @@ mod tests {
         assert_cmp!(Centipawn(-1) ;< Centipawn(9),Centipawn(0));
     }
 
+    #[test]
+    fn test_tb_score_tt() {
+        // A win found 7 plies from the root at a node 4 plies deep is 3 plies away from that node
+        assert_eq!(Score::tb_win(7).to_tt(4), Score::tb_win(3));
+        assert_eq!(Score::tb_win(3).from_tt(2), Score::tb_win(5));
+        assert_eq!(Score::tb_loss(7).to_tt(4), Score::tb_loss(3));
+        assert_eq!(Score::tb_loss(7).to_tt(4).from_tt(4), Score::tb_loss(7));
+        for score in [Centipawn(150), Centipawn(-900), Mate(3), Score::MAX, Score::MIN] {
+            assert_eq!(score.to_tt(5), score);
+            assert_eq!(score.from_tt(5), score);
+        }
+    }
+
+    #[test]
+    fn test_tb_score_ord() {
+        assert_cmp!(Score::tb_win(3) ;> Score::tb_win(5), Centipawn(5000), Score::tb_loss(1));
+        assert_cmp!(Score::tb_win(3) ;< Mate(9), Score::MAX);
+        assert_cmp!(Score::tb_loss(3) ;> Mate(-2), Score::tb_loss(1), Score::MIN);
+    }
+
     macro_rules! assert_op_eq {
         ($lhs:expr ;+ $rhs:expr, $res:expr) => {
             assert_eq!($lhs + $rhs, $res);
@@ -243,4 +412,18 @@ mod tests {
         assert_op_eq!(Centipawn(1) ;- Centipawn(2), Centipawn(-1));
         assert_op_eq!(Centipawn(1) ;+ Mate(3), Mate(3));
     }
+
+    #[test]
+    fn test_packed_score() {
+        let a = S(-20, 35);
+        let b = S(7, -50);
+        assert_eq!((a.mg(), a.eg()), (-20, 35));
+        assert_eq!(a + b, S(-13, -15));
+        assert_eq!(a - b, S(-27, 85));
+        assert_eq!(-a, S(20, -35));
+        assert_eq!(b * 3, S(21, -150));
+        assert_eq!(S(100, 200).taper(MAX_PHASE), Centipawn(100));
+        assert_eq!(S(100, 200).taper(0), Centipawn(200));
+        assert_eq!(S(100, 200).taper(MAX_PHASE / 2), Centipawn(150));
+    }
 }
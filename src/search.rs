@@ -1,139 +1,545 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 use std::time::{Instant, Duration};
 
 use shakmaty::zobrist::{ZobristHash, Zobrist64};
-use shakmaty::{Chess, Position, Move, Outcome, Color, CastlingMode};
+use shakmaty::{Color, Move, Outcome};
 use crate::evaluation::*;
 use crate::score::Score;
 use crate::transposition::{TranspositionTable, NodeType};
+use crate::history::{history_bonus, CaptureHistory, ContinuationHistory, CounterMoves, PieceTo};
+use crate::see::see_ge;
+use crate::skill::{eval_noise, Skill, MAX_LEVEL};
+use crate::movepick::MovePicker;
+use crate::pawn_table::PawnTable;
+use crate::tablebase::{Tablebase, Wdl};
+use crate::utils::Prng;
+use crate::variant::EnginePosition;
 //use crate::utils::signum;
 
+#[derive(Default)]
+pub struct SearchLimits {
+    pub time: Option<Duration>,
+    pub depth: Option<i16>,
+    // Keep searching until stopped, even after the last depth
+    pub infinite: bool,
+    // Start as a ponder search, timed only after ponderhit
+    pub ponder: bool,
+    pub nodes: Option<u64>,
+}
+
+// Lets another thread stop a running search, or turn a ponder search into a timed one
+#[derive(Default)]
+pub struct SearchControl {
+    stop: AtomicBool,
+    pondering: AtomicBool,
+}
+
+impl SearchControl {
+    pub fn new(pondering: bool) -> Self {
+        Self { stop: AtomicBool::new(false), pondering: AtomicBool::new(pondering) }
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    // The time limit applies from here on, counting the time already spent pondering
+    pub fn ponderhit(&self) {
+        self.pondering.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.load(Ordering::Relaxed)
+    }
+}
+
+// Search features that can be switched off, or on, to measure their effect
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    // Internal iterative reductions: search one ply shallower when there is no TT move
+    pub iir: bool,
+    // Internal iterative deepening: find a first move with a shallower search instead
+    pub iid: bool,
+    // ProbCut: cut nodes where a capture beats beta by the margin in a reduced search
+    pub probcut: bool,
+    pub probcut_margin: i16,
+    // Least remaining depth to try ProbCut at
+    pub probcut_depth: i16,
+    // How much worse than equal a draw is for the side the search is for, in centipawns;
+    // negative values make the engine seek draws
+    pub contempt: i16,
+    // Analysis wants unbiased scores, so contempt is ignored
+    pub analyse_mode: bool,
+    // Weaker play by skill level, where 20 is full strength
+    pub skill_level: u8,
+    // Seed for the random choices of weaker play, taken from the clock when not set
+    pub seed: Option<u64>,
+    // Extra diagnostics as messages, as for UCI debug on
+    pub debug: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            iir: true,
+            iid: false,
+            probcut: true,
+            probcut_margin: 200,
+            probcut_depth: 5,
+            contempt: 0,
+            analyse_mode: false,
+            skill_level: MAX_LEVEL,
+            seed: None,
+            debug: false,
+        }
+    }
+}
+
+const PROBCUT_REDUCTION: i16 = 4;
+
+// Captures losing more than this per ply of remaining depth, by static exchange, are pruned
+const SEE_PRUNING_DEPTH: i16 = 6;
+const SEE_CAPTURE_MARGIN: i32 = 100;
+
+const IIR_DEPTH: i16 = 4;
+const IID_DEPTH: i16 = 5;
+const IID_REDUCTION: i16 = 2;
+
+// Whether a reported score is exact or only a bound from a failed aspiration window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+// Progress of a running search, left to the front end to format
+#[derive(Debug, Clone)]
+pub enum SearchInfo {
+    Iteration {
+        depth: i16,
+        seldepth: i16,
+        score: Score,
+        bound: Bound,
+        nodes: u64,
+        time: Duration,
+        tb_hits: u64,
+        // Permille of the transposition table in use
+        hashfull: u16,
+        pv: Vec<Move>,
+    },
+    // The root move being searched, numbered from 1
+    CurrMove { depth: i16, mov: Move, number: usize },
+    Message(String),
+}
+
+pub trait SearchObserver {
+    fn on_info(&mut self, info: &SearchInfo);
+}
+
+impl<F: FnMut(&SearchInfo)> SearchObserver for F {
+    fn on_info(&mut self, info: &SearchInfo) {
+        self(info)
+    }
+}
+
+// Discards all progress
+impl SearchObserver for () {
+    fn on_info(&mut self, _: &SearchInfo) {}
+}
+
+const TIME_CHECK_NODES: u64 = 1024;
+
+// Root moves are only reported once the search has been running this long
+const CURRMOVE_DELAY: Duration = Duration::from_secs(1);
+
+pub struct SearchResult {
+    // None when there is no legal move
+    pub best_move: Option<Move>,
+    pub pv: Vec<Move>,
+}
+
+// Tables and counters shared by all nodes of one search
+struct SearchContext<'a> {
+    transposition_table: &'a mut TranspositionTable,
+    pawn_table: PawnTable,
+    tablebase: &'a Tablebase,
+    options: &'a SearchOptions,
+    control: &'a SearchControl,
+    observer: &'a mut dyn SearchObserver,
+    start_time: Instant,
+    // The side to move at the root, which contempt is relative to
+    root_turn: Color,
+    root_moves: Vec<Move>,
+    // Root moves already searched as better lines, skipped when looking for the next one
+    excluded_root_moves: Vec<Move>,
+    nodes: u64,
+    node_limit: Option<u64>,
+    time_limit: Option<Duration>,
+    // Set once the time limit has passed, which is only checked every few nodes
+    out_of_time: bool,
+    // Evaluation noise of weaker skill levels
+    noise_seed: u64,
+    noise_amplitude: i16,
+    tb_hits: u64,
+    seldepth: i16,
+    // Triangular PV table: the best line found from each ply of the current path
+    pv_table: Vec<Vec<Move>>,
+    // Search stack: the move played at each ply of the current path
+    stack: Vec<Option<PieceTo>>,
+    counter_moves: CounterMoves,
+    // Follow-up scores for the moves one and two plies back
+    continuation_history: [ContinuationHistory; 2],
+    capture_history: CaptureHistory,
+    // Set once the first iteration is done, so that a stopped search still has a move
+    can_abort: bool,
+}
+
+impl<'a> SearchContext<'a> {
+    fn new(
+        transposition_table: &'a mut TranspositionTable,
+        tablebase: &'a Tablebase,
+        options: &'a SearchOptions,
+        control: &'a SearchControl,
+        observer: &'a mut dyn SearchObserver,
+    ) -> Self {
+        Self {
+            transposition_table,
+            pawn_table: PawnTable::new(1 << 14),
+            tablebase,
+            options,
+            control,
+            observer,
+            start_time: Instant::now(),
+            root_turn: Color::White,
+            root_moves: Vec::new(),
+            excluded_root_moves: Vec::new(),
+            nodes: 0,
+            node_limit: None,
+            time_limit: None,
+            out_of_time: false,
+            noise_seed: 0,
+            noise_amplitude: 0,
+            tb_hits: 0,
+            seldepth: 0,
+            pv_table: vec![Vec::new(); MAX_PLY + 1],
+            stack: vec![None; MAX_PLY + 1],
+            counter_moves: CounterMoves::new(),
+            continuation_history: [ContinuationHistory::new(), ContinuationHistory::new()],
+            capture_history: CaptureHistory::new(),
+            can_abort: false,
+        }
+    }
+
+    fn aborted(&self) -> bool {
+        self.can_abort
+            && (self.control.is_stopped() || self.out_of_time || self.node_limit.is_some_and(|limit| self.nodes >= limit))
+    }
+
+    // The clock is only read every few nodes, and not while pondering
+    fn count_node(&mut self) {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(TIME_CHECK_NODES)
+            && let Some(limit) = self.time_limit
+            && !self.control.is_pondering()
+        {
+            self.out_of_time = self.start_time.elapsed() > limit;
+        }
+    }
+
+    // Score of a draw for the side to move: a loss by the contempt for the root side,
+    // and so a win by as much for its opponent
+    fn draw_score(&self, turn: Color) -> Score {
+        let contempt = if self.options.analyse_mode { 0 } else { self.options.contempt };
+        Score::Centipawn(if turn == self.root_turn { -contempt } else { contempt })
+    }
+}
 
-fn quiescence_search(pos: &Chess, mut alpha: Score, beta: Score) -> Score {
+// Score of a finished game for the side to move
+fn game_over_score<P: EnginePosition>(pos: &P, ctx: &mut SearchContext) -> Score {
+    match pos.outcome() {
+        Some(Outcome::Draw) => ctx.draw_score(pos.turn()),
+        _ => calculate_score(pos, &mut ctx.pawn_table).apply_color_factor(pos.turn()),
+    }
+}
+
+// Quiet checks are only tried at the first quiescence ply, whose depth is 0
+fn quiescence_search<P: EnginePosition>(
+    pos: &P,
+    depth: i16,
+    ply: i16,
+    mut alpha: Score,
+    beta: Score,
+    ctx: &mut SearchContext,
+) -> Score {
+    ctx.count_node();
+    ctx.seldepth = ctx.seldepth.max(ply);
     if pos.is_game_over() {
-        return match pos.outcome().unwrap() {
-            Outcome::Decisive { winner } => Score::Mate(1).apply_color_factor(winner),
-            Outcome::Draw => Score::ZERO,
-        };
+        return game_over_score(pos, ctx);
+    }
+
+    let hash = pos.zobrist_hash::<Zobrist64>(shakmaty::EnPassantMode::Legal).into();
+    let alpha_orig = alpha;
+    let mut tt_move = None;
+    if let Some(entry) = ctx.transposition_table.get(hash) {
+        let score = entry.score.from_tt(ply);
+        if entry.depth >= depth {
+            match entry.node_type {
+                NodeType::Exact => return score,
+                NodeType::LowerBound if score >= beta => return score,
+                NodeType::UpperBound if score <= alpha => return score,
+                _ => {}
+            }
+        }
+        tt_move = entry.best_move.clone();
     }
 
     let is_in_check = pos.is_check();
     let stand_pat = if is_in_check {
         Score::MIN
     } else {
-        calculate_score(pos).apply_color_factor(pos.turn())
+        let noise = eval_noise(hash, ctx.noise_seed, ctx.noise_amplitude);
+        calculate_score(pos, &mut ctx.pawn_table).apply_color_factor(pos.turn()) + Score::Centipawn(noise)
     };
 
     if !is_in_check {
         if stand_pat >= beta {
-            return beta;
+            ctx.transposition_table.insert(hash, depth, stand_pat.to_tt(ply), NodeType::LowerBound, None);
+            return stand_pat;
         }
         alpha = alpha.max(stand_pat);
     }
 
     let mut moves = pos.legal_moves();
     if !is_in_check {
-        moves.retain(|m| m.is_capture());
-        // Delta pruning: skip captures that can't improve alpha
+        // Delta pruning: skip captures that can't improve alpha, or that lose material
         let delta_margin = Score::Centipawn(100);
-        moves.retain(|m| {
-            if let Some(captured) = m.capture() {
-                stand_pat + get_piece_value(captured) + delta_margin > alpha
-            } else {
-                true
+        let use_see = pos.as_chess().is_some();
+        moves.retain(|m| match m.capture() {
+            Some(captured) => {
+                stand_pat + get_piece_value(captured) + delta_margin > alpha && (!use_see || see_ge(pos.board(), m, 0))
             }
+            None => depth == 0 && m.promotion().is_none() && gives_check(pos, m),
         });
     }
 
-    // MVV-LVA ordering for captures, others by piece value in check
-    moves.sort_by(|a, b| {
-        let a_cap = a.capture();
-        let b_cap = b.capture();
-        match (a_cap, b_cap) {
-            (Some(a_piece), Some(b_piece)) => {
-                let a_val = get_piece_value(a_piece) * 10 - get_piece_value(a.role());
-                let b_val = get_piece_value(b_piece) * 10 - get_piece_value(b.role());
-                b_val.cmp(&a_val)
-            }
-            (Some(_), None) => std::cmp::Ordering::Less,
-            (None, Some(_)) => std::cmp::Ordering::Greater,
-            (None, None) => b.role().cmp(&a.role()), // Higher value pieces first in checks
-        }
+    // Captures by victim and capture history, others by piece value in check
+    let picker = MovePicker::new(moves, tt_move.as_ref(), |m| {
+        if m.is_capture() { 1_000_000 + capture_order(ctx, pos.turn(), m) } else { m.role() as i32 }
     });
 
-    for mov in moves {
+    let mut best_move = None;
+    for mov in picker {
         let mut new_pos = pos.clone();
         new_pos.play_unchecked(&mov);
-        let score = -quiescence_search(&new_pos, -beta, -alpha);
-        
+        // Mates found here count the plies to them, like in the main search
+        let score = -quiescence_search(&new_pos, depth - 1, ply + 1, -beta, -alpha, ctx).increment_mate_depth();
+
         if score >= beta {
-            return beta;
+            // Fail soft, so that a mate is not clipped to the window
+            ctx.transposition_table.insert(hash, depth, score.to_tt(ply), NodeType::LowerBound, Some(mov));
+            return score;
         }
         if score > alpha {
             alpha = score;
+            best_move = Some(mov);
         }
     }
 
+    let node_type = if alpha > alpha_orig { NodeType::Exact } else { NodeType::UpperBound };
+    ctx.transposition_table.insert(hash, depth, alpha.to_tt(ply), node_type, best_move);
     alpha
 }
 
-fn negamax(
-    pos: &Chess,
-    depth: i16,
+fn gives_check<P: EnginePosition>(pos: &P, mov: &Move) -> bool {
+    let mut new_pos = pos.clone();
+    new_pos.play_unchecked(mov);
+    new_pos.is_check()
+}
+
+fn negamax<P: EnginePosition>(
+    pos: &P,
+    mut depth: i16,
     ply: i16,
     mut alpha: Score,
     beta: Score,
-    transposition_table: &mut TranspositionTable,
+    ctx: &mut SearchContext,
 ) -> (Score, Option<Move>) {
+    ctx.count_node();
+    ctx.seldepth = ctx.seldepth.max(ply);
+    // Nodes that return early leave an empty line behind them
+    ctx.pv_table[ply as usize].clear();
+    if ctx.aborted() {
+        return (Score::ZERO, None);
+    }
+
     let hash = pos.zobrist_hash::<Zobrist64>(shakmaty::EnPassantMode::Legal).into();
     let alpha_orig = alpha;
+    // A root search that skips some moves has a different result from the one in the table
+    let excluding = ply == 0 && !ctx.excluded_root_moves.is_empty();
 
-    if let Some(entry) = transposition_table.get(hash) {
-        if entry.depth >= depth {
-            match entry.node_type {
-                NodeType::Exact => return (entry.score, entry.best_move.clone()),
-                NodeType::LowerBound if entry.score >= beta => return (entry.score, entry.best_move.clone()),
-                NodeType::UpperBound if entry.score <= alpha => return (entry.score, entry.best_move.clone()),
-                _ => {}
-            }
+    if !excluding
+        && let Some(entry) = ctx.transposition_table.get(hash)
+        && entry.depth >= depth
+    {
+        let score = entry.score.from_tt(ply);
+        match entry.node_type {
+            NodeType::Exact => return (score, entry.best_move.clone()),
+            NodeType::LowerBound if score >= beta => return (score, entry.best_move.clone()),
+            NodeType::UpperBound if score <= alpha => return (score, entry.best_move.clone()),
+            _ => {}
         }
     }
 
     if pos.is_game_over() {
-        let eval = calculate_score(pos).apply_color_factor(pos.turn());
-        return (eval, None);
+        return (game_over_score(pos, ctx), None);
+    }
+
+    // Tablebase probe, only right after a capture or pawn move so that the result
+    // is not spoiled by the fifty-move rule
+    if ply > 0
+        && pos.halfmoves() == 0
+        && pos.board().occupied().count() <= ctx.tablebase.max_pieces()
+        && let Some(chess) = pos.as_chess()
+        && let Some(wdl) = ctx.tablebase.probe_wdl(chess)
+    {
+        ctx.tb_hits += 1;
+        let (value, node_type) = match wdl {
+            Wdl::Win => (Score::tb_win(ply), NodeType::LowerBound),
+            Wdl::Draw => (ctx.draw_score(pos.turn()), NodeType::Exact),
+            Wdl::Loss => (Score::tb_loss(ply), NodeType::UpperBound),
+        };
+        if node_type == NodeType::Exact
+            || (node_type == NodeType::LowerBound && value >= beta)
+            || (node_type == NodeType::UpperBound && value <= alpha)
+        {
+            ctx.transposition_table.insert(hash, depth, value.to_tt(ply), node_type, None);
+            return (value, None);
+        }
     }
 
     if depth <= 0 {
-        return (quiescence_search(pos, alpha, beta), None);
+        return (quiescence_search(pos, 0, ply, alpha, beta, ctx), None);
+    }
+
+    // Without a move to try first, move ordering is poor and a full-depth search is mostly wasted
+    let has_tt_move = ctx.transposition_table.get(hash).is_some_and(|entry| entry.best_move.is_some());
+    if ply > 0 && !has_tt_move {
+        if ctx.options.iid && depth >= IID_DEPTH {
+            negamax(pos, depth - IID_REDUCTION, ply, alpha, beta, ctx);
+            ctx.pv_table[ply as usize].clear();
+            if ctx.aborted() {
+                return (Score::ZERO, None);
+            }
+        } else if ctx.options.iir && depth >= IIR_DEPTH {
+            depth -= 1;
+        }
+    }
+
+    let tt_move = ctx.transposition_table.get(hash).and_then(|entry| entry.best_move.clone());
+    let is_in_check = pos.is_check();
+    let use_see = pos.as_chess().is_some();
+
+    // ProbCut: when a capture beats beta by a margin in a shallow search, the full search
+    // would almost surely fail high as well
+    if ctx.options.probcut
+        && ply > 0
+        && depth >= ctx.options.probcut_depth
+        && is_null_window(alpha, beta)
+        && !is_in_check
+        && use_see
+        && let Score::Centipawn(_) = beta
+    {
+        let probcut_beta = beta + Score::Centipawn(ctx.options.probcut_margin);
+        let static_eval = calculate_score(pos, &mut ctx.pawn_table).apply_color_factor(pos.turn());
+        if let Score::Centipawn(gain) = probcut_beta - static_eval {
+            let picker = MovePicker::probcut(pos.legal_moves(), pos.board(), tt_move.as_ref(), gain as i32, |m| {
+                capture_order(ctx, pos.turn(), m)
+            });
+            for mov in picker {
+                let mut new_pos = pos.clone();
+                new_pos.play_unchecked(&mov);
+                ctx.stack[ply as usize] = Some(PieceTo::new(pos.turn(), &mov));
+
+                // A quiescence search first weeds out captures that do not hold up
+                let mut value = -quiescence_search(&new_pos, 0, ply + 1, -probcut_beta, -probcut_beta + 1, ctx).increment_mate_depth();
+                if value >= probcut_beta {
+                    let reduced = depth - PROBCUT_REDUCTION;
+                    value = -negamax(&new_pos, reduced, ply + 1, -probcut_beta, -probcut_beta + 1, ctx).0.increment_mate_depth();
+                }
+                if ctx.aborted() {
+                    return (Score::ZERO, None);
+                }
+                if value >= probcut_beta {
+                    ctx.transposition_table.insert(hash, depth - PROBCUT_REDUCTION + 1, value.to_tt(ply), NodeType::LowerBound, Some(mov.clone()));
+                    return (value, Some(mov));
+                }
+            }
+        }
     }
 
     let mut best_value = Score::MIN;
     let mut best_move = None;
     let mut moves = pos.legal_moves();
+    if ply == 0 && !ctx.root_moves.is_empty() {
+        moves.retain(|m| ctx.root_moves.contains(m));
+    }
+    if excluding {
+        moves.retain(|m| !ctx.excluded_root_moves.contains(m));
+    }
+
+    // The moves that led here, one and two plies back
+    let previous: [Option<PieceTo>; 2] = [1, 2].map(|back| if ply >= back { ctx.stack[(ply - back) as usize] } else { None });
+    let counter_move = previous[0].and_then(|prev| ctx.counter_moves.get(prev).cloned());
 
-    moves.sort_by_cached_key(|m| {
-        let mut score = Score::ZERO;
+    // TT move, captures and promotions first, then the counter move, then quiet moves by continuation history
+    let picker = MovePicker::new(moves, tt_move.as_ref(), |m| {
         if m.is_capture() {
-            score += Score::Centipawn(1000) + get_piece_value(m.capture().unwrap());
-        }
-        if m.promotion().is_some() {
-            score += Score::Centipawn(900);
+            1_000_000 + capture_order(ctx, pos.turn(), m)
+        } else if m.promotion().is_some() {
+            900_000
+        } else if counter_move.as_ref() == Some(m) {
+            800_000
+        } else {
+            let current = PieceTo::new(pos.turn(), m);
+            (0..2)
+                .filter_map(|i| Some(ctx.continuation_history[i].get(previous[i]?, current)))
+                .sum()
         }
-        -score
     });
 
-    if let Some(entry) = transposition_table.get(hash) {
-        if let Some(bm) = &entry.best_move {
-            if let Some(idx) = moves.iter().position(|m| m == bm) {
-                moves.swap(0, idx);
+    let mut first_move = true;
+    let mut quiets_tried = Vec::new();
+    let mut captures_tried = Vec::new();
+    for (i, mov) in picker.enumerate() {
+        let mov = &mov;
+        if ply == 0 && ctx.start_time.elapsed() > CURRMOVE_DELAY {
+            ctx.observer.on_info(&SearchInfo::CurrMove { depth, mov: mov.clone(), number: i + 1 });
+        }
+
+        // Near the leaves, skip captures that lose too much material, unless they did well before
+        if ply > 0
+            && i > 0
+            && depth <= SEE_PRUNING_DEPTH
+            && !is_in_check
+            && use_see
+            && let Some(captured) = mov.capture()
+        {
+            let history = ctx.capture_history.get(PieceTo::new(pos.turn(), mov), captured);
+            if !see_ge(pos.board(), mov, -SEE_CAPTURE_MARGIN * depth as i32 - history / 32) {
+                continue;
             }
         }
-    }
 
-    let mut first_move = true;
-    for (i, mov) in moves.iter().enumerate() {
         let mut new_pos = pos.clone();
-        new_pos.play_unchecked(&mov);
+        new_pos.play_unchecked(mov);
+        ctx.stack[ply as usize] = Some(PieceTo::new(pos.turn(), mov));
 
         let is_capture = mov.is_capture();
         let is_promotion = mov.promotion().is_some();
@@ -147,30 +553,47 @@ fn negamax(
         let score;
         
         if first_move {
-            let s = negamax(&new_pos, depth - 1 - depth_reduction, ply + 1, -beta, -alpha, transposition_table).0.increment_mate_depth();
+            let s = negamax(&new_pos, depth - 1 - depth_reduction, ply + 1, -beta, -alpha, ctx).0.increment_mate_depth();
             score = -s;
             first_move = false;
         } else {
-            let s_null = negamax(&new_pos, depth - 1 - depth_reduction, ply + 1, -alpha - 1, -alpha, transposition_table).0.increment_mate_depth();
+            let s_null = negamax(&new_pos, depth - 1 - depth_reduction, ply + 1, -alpha - 1, -alpha, ctx).0.increment_mate_depth();
             let null_score = -s_null;
             if null_score > alpha {
-                let s_research = negamax(&new_pos, depth - 1, ply + 1, -beta, -alpha, transposition_table).0.increment_mate_depth();
+                let s_research = negamax(&new_pos, depth - 1, ply + 1, -beta, -alpha, ctx).0.increment_mate_depth();
                 score = -s_research;
             } else {
                 score = null_score;
             }
         }
         
+        if ctx.aborted() {
+            return (Score::ZERO, None);
+        }
+
         if score > best_value {
             best_value = score;
             best_move = Some(mov.clone());
             if score > alpha {
                 alpha = score;
+                let (line, rest) = ctx.pv_table.split_at_mut(ply as usize + 1);
+                let line = &mut line[ply as usize];
+                line.clear();
+                line.push(mov.clone());
+                if let Some(child) = rest.first() {
+                    line.extend(child.iter().cloned());
+                }
                 if alpha >= beta {
+                    update_history(ctx, pos.turn(), &previous, mov, &quiets_tried, &captures_tried, depth);
                     break;
                 }
             }
         }
+        if is_capture {
+            captures_tried.push(mov.clone());
+        } else if !is_promotion {
+            quiets_tried.push(mov.clone());
+        }
     }
 
     let node_type = if best_value <= alpha_orig {
@@ -180,63 +603,167 @@ fn negamax(
     } else {
         NodeType::Exact
     };
-    transposition_table.insert(hash, depth, best_value, node_type, best_move.clone());
+    if !excluding {
+        ctx.transposition_table.insert(hash, depth, best_value.to_tt(ply), node_type, best_move.clone());
+    }
 
     (best_value, best_move)
 }
 
-fn get_principal_variation(pos: &Chess, tt: &TranspositionTable) -> Vec<Move> {
+// Non-PV nodes are searched with a window of one centipawn
+fn is_null_window(alpha: Score, beta: Score) -> bool {
+    matches!((alpha, beta), (Score::Centipawn(a), Score::Centipawn(b)) if b as i32 - a as i32 == 1)
+}
+
+// Most valuable victim first, captures that did well before break ties
+fn capture_order(ctx: &SearchContext, turn: shakmaty::Color, mov: &Move) -> i32 {
+    let captured = mov.capture().unwrap();
+    PIECE_VALUES[captured as usize] as i32 * 16 + ctx.capture_history.get(PieceTo::new(turn, mov), captured)
+}
+
+// Rewards the move that caused a cutoff and penalizes the moves of its kind tried before it.
+// Captures tried before any cutoff move are penalized too.
+fn update_history(
+    ctx: &mut SearchContext,
+    turn: shakmaty::Color,
+    previous: &[Option<PieceTo>; 2],
+    best: &Move,
+    quiets_tried: &[Move],
+    captures_tried: &[Move],
+    depth: i16,
+) {
+    let bonus = history_bonus(depth);
+    for mov in captures_tried {
+        ctx.capture_history.update(PieceTo::new(turn, mov), mov.capture().unwrap(), -bonus);
+    }
+    if let Some(captured) = best.capture() {
+        ctx.capture_history.update(PieceTo::new(turn, best), captured, bonus);
+        return;
+    }
+    if best.promotion().is_some() {
+        return;
+    }
+
+    for (i, prev) in previous.iter().enumerate() {
+        let Some(prev) = *prev else { continue };
+        ctx.continuation_history[i].update(prev, PieceTo::new(turn, best), bonus);
+        for mov in quiets_tried {
+            ctx.continuation_history[i].update(prev, PieceTo::new(turn, mov), -bonus);
+        }
+    }
+    if let Some(prev) = previous[0] {
+        ctx.counter_moves.set(prev, best.clone());
+    }
+}
+
+// Longest line the search can collect, and the longest one reported
+const MAX_PLY: usize = 128;
+
+// The line collected by the last root search, extended with transposition table moves
+// as long as they are legal and do not repeat a position
+fn get_principal_variation<P: EnginePosition>(pos: &P, ctx: &SearchContext) -> Vec<Move> {
     let mut pv = Vec::new();
     let mut current_pos = pos.clone();
-    let mut current_hash = current_pos.zobrist_hash::<Zobrist64>(shakmaty::EnPassantMode::Legal).into();
-
-    loop {
-        let entry = tt.get(current_hash);
-        if let Some(entry) = entry {
-            if let Some(best_move) = &entry.best_move {
-                pv.push(best_move.clone());
-                current_pos.play_unchecked(best_move);
-                current_hash = current_pos.zobrist_hash::<Zobrist64>(shakmaty::EnPassantMode::Legal).into();
-            } else {
-                break;
-            }
-        } else {
+    for mov in &ctx.pv_table[0] {
+        if !current_pos.is_legal(mov) {
             break;
         }
+        current_pos.play_unchecked(mov);
+        pv.push(mov.clone());
+    }
 
-        if pv.len() >= 100 {
+    let mut seen = HashSet::new();
+    while pv.len() < MAX_PLY && !current_pos.is_game_over() {
+        let hash: u64 = current_pos.zobrist_hash::<Zobrist64>(shakmaty::EnPassantMode::Legal).into();
+        if !seen.insert(hash) {
+            break;
+        }
+        let Some(mov) = ctx.transposition_table.get(hash).and_then(|entry| entry.best_move.clone()) else {
+            break;
+        };
+        if !current_pos.is_legal(&mov) {
             break;
         }
+        current_pos.play_unchecked(&mov);
+        pv.push(mov);
     }
 
     pv
 }
 
-pub fn find_best_move(pos: &Chess, remaining_time: i32) -> (Move, Score) {
-    let start_time = Instant::now();
-    let time_budget = Duration::from_millis(remaining_time as u64 / 40);
-    let mut transposition_table = TranspositionTable::new(1 << 20);
+pub fn find_best_move<P: EnginePosition>(
+    pos: &P,
+    limits: &SearchLimits,
+    transposition_table: &mut TranspositionTable,
+    tablebase: &Tablebase,
+    options: &SearchOptions,
+    control: &SearchControl,
+    observer: &mut dyn SearchObserver,
+) -> SearchResult {
+    transposition_table.new_search();
+    let mut ctx = SearchContext::new(transposition_table, tablebase, options, control, observer);
+    ctx.root_turn = pos.turn();
+
+    // In a tablebase position only search the moves that keep the result
+    if pos.castles().is_empty()
+        && pos.board().occupied().count() <= tablebase.max_pieces()
+        && let Some(chess) = pos.as_chess()
+        && let Some((_, moves)) = tablebase.probe_root(chess)
+    {
+        ctx.tb_hits += 1;
+        ctx.root_moves = moves;
+    }
+
+    // Weaker skill levels search less, with a noisy evaluation, then pick among the best lines
+    let skill = Skill::from_options(options);
+    let mut rng = options.seed.map_or_else(Prng::from_time, Prng::new);
+    let mut max_depth = limits.depth.unwrap_or(50);
+    ctx.node_limit = limits.nodes;
+    ctx.time_limit = limits.time;
+    if let Some(skill) = &skill {
+        max_depth = max_depth.min(skill.depth());
+        ctx.node_limit = Some(ctx.node_limit.map_or(skill.nodes(), |nodes| nodes.min(skill.nodes())));
+        ctx.noise_seed = rng.next_u64();
+        ctx.noise_amplitude = skill.noise_amplitude();
+    }
+    // The best root lines of the last complete iteration, for the skill level to choose from
+    let mut lines = Vec::new();
+
     let mut best_move = None;
     let mut best_score = Score::MIN;
+    let mut pv = Vec::new();
     let mut current_depth = 1;
 
-    while current_depth <= 50 {
+    while current_depth <= max_depth {
         let window = if current_depth >= 2 { Score::Centipawn(100) } else { Score::Centipawn(1000) };
         let mut alpha = best_score - window;
         let mut beta = best_score + window;
 
-        if current_depth == 1 {
+        // Mate scores ignore the window arithmetic, so they are searched with a full window
+        if current_depth == 1 || matches!(best_score, Score::Mate(_)) {
             alpha = -Score::MAX / 2;
             beta = Score::MAX / 2;
         }
 
-        let (mut score, mut mv) = negamax(pos, current_depth, 0, alpha, beta, &mut transposition_table);
+        ctx.seldepth = 0;
+        let (mut score, mut mv) = negamax(pos, current_depth, 0, alpha, beta, &mut ctx);
+
+        // Report the failed bound before searching again with a wider window
+        let bound = window_bound(score, alpha, beta);
+        if bound != Bound::Exact && !ctx.aborted() {
+            report_iteration(pos, current_depth, score, bound, &mut ctx);
+        }
+        if bound == Bound::Upper {
+            alpha = -Score::MAX / 2;
+            (score, mv) = negamax(pos, current_depth, 0, alpha, beta, &mut ctx);
+        } else if bound == Bound::Lower {
+            beta = Score::MAX / 2;
+            (score, mv) = negamax(pos, current_depth, 0, alpha, beta, &mut ctx);
+        }
 
-        if score <= alpha {
-            (score, mv) = negamax(pos, current_depth, 0, -Score::MAX / 2, beta, &mut transposition_table);
-        } else if score >= beta {
-            (score, mv) = negamax(pos, current_depth, 0, alpha, Score::MAX / 2, &mut transposition_table);
-            
+        // A stopped iteration is incomplete, so its result is dropped
+        if ctx.aborted() {
+            break;
         }
 
         if let Some(m) = mv {
@@ -244,25 +771,301 @@ pub fn find_best_move(pos: &Chess, remaining_time: i32) -> (Move, Score) {
             best_score = score;
         }
 
-        let pv = get_principal_variation(pos, &transposition_table);
-        let pv_uci: Vec<String> = pv.iter()
-            .map(|m| m.to_uci(CastlingMode::Standard).to_string())
-            .collect();
-        let pv_str = pv_uci.join(" ");
+        // Checkmate or stalemate at the root, there is nothing to search
+        if best_move.is_none() {
+            break;
+        }
+
+        pv = report_iteration(pos, current_depth, best_score, window_bound(score, alpha, beta), &mut ctx);
 
-        println!(
-            "info depth {} {} time {} pv {}",
-            current_depth,
-            best_score,
-            start_time.elapsed().as_millis(),
-            pv_str
-        );
+        if let Some(skill) = &skill
+            && let Some(best) = &best_move
+        {
+            let mut iteration_lines = vec![(best.clone(), best_score)];
+            ctx.excluded_root_moves.push(best.clone());
+            while iteration_lines.len() < skill.multi_pv() {
+                let (score, mv) = negamax(pos, current_depth, 0, -Score::MAX / 2, Score::MAX / 2, &mut ctx);
+                let Some(mv) = mv.filter(|_| !ctx.aborted()) else { break };
+                ctx.excluded_root_moves.push(mv.clone());
+                iteration_lines.push((mv, score));
+            }
+            ctx.excluded_root_moves.clear();
+            if ctx.aborted() {
+                break;
+            }
+            lines = iteration_lines;
+        }
+        ctx.can_abort = true;
 
-        if start_time.elapsed() > time_budget {
+        if control.is_stopped()
+            || (!control.is_pondering() && limits.time.is_some_and(|time| ctx.start_time.elapsed() > time))
+        {
             break;
         }
         current_depth += 1;
     }
 
-    (best_move.expect("No legal moves"), best_score)
-}
\ No newline at end of file
+    // Pondering and infinite searches wait to be stopped before returning a move
+    while (limits.infinite || control.is_pondering()) && !control.is_stopped() {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    if options.debug {
+        ctx.observer.on_info(&SearchInfo::Message(format!(
+            "pawn hash hit rate {:.1}%",
+            ctx.pawn_table.hit_rate() * 100.0
+        )));
+    }
+
+    let Some(mut best_move) = best_move else {
+        return SearchResult { best_move: None, pv: Vec::new() };
+    };
+    if let Some(skill) = &skill
+        && !lines.is_empty()
+    {
+        best_move = skill.pick_move(&lines, &mut rng);
+    }
+    if pv.first() != Some(&best_move) {
+        pv = vec![best_move.clone()];
+    }
+    SearchResult { best_move: Some(best_move), pv }
+}
+
+// Scores outside the aspiration window are bounds; the full window has no bounds
+fn window_bound(score: Score, alpha: Score, beta: Score) -> Bound {
+    if score <= alpha && alpha != -Score::MAX / 2 {
+        Bound::Upper
+    } else if score >= beta && beta != Score::MAX / 2 {
+        Bound::Lower
+    } else {
+        Bound::Exact
+    }
+}
+
+// Sends the state after a root search to the observer and returns its principal variation
+fn report_iteration<P: EnginePosition>(pos: &P, depth: i16, score: Score, bound: Bound, ctx: &mut SearchContext) -> Vec<Move> {
+    let pv = get_principal_variation(pos, ctx);
+    ctx.observer.on_info(&SearchInfo::Iteration {
+        depth,
+        seldepth: ctx.seldepth,
+        score,
+        bound,
+        nodes: ctx.nodes,
+        time: ctx.start_time.elapsed(),
+        tb_hits: ctx.tb_hits,
+        hashfull: ctx.transposition_table.hashfull(),
+        pv: pv.clone(),
+    });
+    pv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::position;
+    use shakmaty::{CastlingMode, Chess, Position};
+
+    fn search(pos: &Chess, limits: &SearchLimits, observer: &mut dyn SearchObserver) -> SearchResult {
+        search_with(pos, limits, &SearchOptions::default(), observer)
+    }
+
+    fn search_with(pos: &Chess, limits: &SearchLimits, options: &SearchOptions, observer: &mut dyn SearchObserver) -> SearchResult {
+        let mut transposition_table = TranspositionTable::new(1 << 20);
+        find_best_move(pos, limits, &mut transposition_table, &Tablebase, options, &SearchControl::default(), observer)
+    }
+
+    #[test]
+    fn test_observer_events() {
+        let pos = position("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        let limits = SearchLimits { depth: Some(3), ..Default::default() };
+        let mut events = Vec::new();
+        let mut observer = |info: &SearchInfo| events.push(info.clone());
+        let result = search(&pos, &limits, &mut observer);
+
+        let mut depths = Vec::new();
+        for event in &events {
+            if let SearchInfo::Iteration { depth, seldepth, bound: Bound::Exact, hashfull, pv, .. } = event {
+                depths.push(*depth);
+                assert!(*seldepth >= 1 && *hashfull <= 1000);
+                assert!(!pv.is_empty());
+            }
+        }
+        assert_eq!(depths, [1, 2, 3]);
+        let Some(SearchInfo::Iteration { score, pv, .. }) =
+            events.iter().rfind(|event| matches!(event, SearchInfo::Iteration { .. }))
+        else {
+            unreachable!()
+        };
+        assert_eq!(score.to_string(), "score mate 1");
+        assert_eq!(Some(&pv[0]), result.best_move.as_ref());
+    }
+
+    #[test]
+    fn test_pv_is_legal() {
+        let pos = position("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4");
+        let limits = SearchLimits { depth: Some(5), ..Default::default() };
+        let mut lines = Vec::new();
+        let mut observer = |info: &SearchInfo| {
+            if let SearchInfo::Iteration { depth, pv, .. } = info {
+                lines.push((*depth, pv.clone()));
+            }
+        };
+        search(&pos, &limits, &mut observer);
+
+        for (depth, pv) in lines {
+            assert!(pv.len() >= depth as usize);
+            let mut current = pos.clone();
+            for mov in pv {
+                assert!(current.is_legal(&mov));
+                current.play_unchecked(&mov);
+            }
+        }
+    }
+
+    #[test]
+    fn test_quiescence_checks_and_mates() {
+        let tablebase = Tablebase;
+        let options = SearchOptions::default();
+        let control = SearchControl::default();
+        let mut observer = ();
+        let mut tt = TranspositionTable::new(1 << 16);
+        let mut ctx = SearchContext::new(&mut tt, &tablebase, &options, &control, &mut observer);
+        let (alpha, beta) = (-Score::MAX / 2, Score::MAX / 2);
+
+        // Ra8# is a quiet check, only tried at the first quiescence ply
+        let pos = position("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        let score = quiescence_search(&pos, 0, 0, alpha, beta, &mut ctx);
+        assert_eq!(score.to_string(), "score mate 1");
+        let mut tt = TranspositionTable::new(1 << 16);
+        let mut ctx = SearchContext::new(&mut tt, &tablebase, &options, &control, &mut observer);
+        assert!(matches!(quiescence_search(&pos, -1, 0, alpha, beta, &mut ctx), Score::Centipawn(_)));
+
+        // Mated positions score as lost for the side to move, whichever color it is
+        for fen in ["R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", "6k1/8/8/8/8/8/5PPP/r5K1 w - - 0 1"] {
+            let score = quiescence_search(&position(fen), 0, 0, alpha, beta, &mut ctx);
+            assert!(score.is_negative() && matches!(score, Score::Mate(_)));
+        }
+        // The result is stored for the next visit
+        let hash = pos.zobrist_hash::<Zobrist64>(shakmaty::EnPassantMode::Legal).into();
+        assert!(ctx.transposition_table.get(hash).is_some());
+    }
+
+    #[test]
+    fn test_iir_and_iid_options() {
+        let pos = position("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4");
+        let tablebase = Tablebase;
+        let control = SearchControl::default();
+        let mut node_counts = Vec::new();
+        for (iir, iid) in [(false, false), (true, false), (false, true)] {
+            let options = SearchOptions { iir, iid, probcut: false, ..Default::default() };
+            let mut observer = ();
+            let mut tt = TranspositionTable::new(1 << 16);
+            let mut ctx = SearchContext::new(&mut tt, &tablebase, &options, &control, &mut observer);
+            // Below the root and with an empty table, so no node starts with a TT move
+            let (_, best_move) = negamax(&pos, 5, 1, -Score::MAX / 2, Score::MAX / 2, &mut ctx);
+            assert!(pos.is_legal(&best_move.unwrap()));
+            node_counts.push(ctx.nodes);
+        }
+        // Each option changes the shape of the tree
+        assert_ne!(node_counts[1], node_counts[0]);
+        assert_ne!(node_counts[2], node_counts[0]);
+    }
+
+    #[test]
+    fn test_probcut_cuts_winning_capture() {
+        // Rxd5 wins the queen, far above a beta of zero
+        let pos = position("4k3/pp6/8/3q4/8/8/PP1R4/3RK3 w - - 0 1");
+        let tablebase = Tablebase;
+        let control = SearchControl::default();
+        let mut results = Vec::new();
+        for probcut in [false, true] {
+            let options = SearchOptions { probcut, ..Default::default() };
+            let mut observer = ();
+            let mut tt = TranspositionTable::new(1 << 16);
+            let mut ctx = SearchContext::new(&mut tt, &tablebase, &options, &control, &mut observer);
+            let (score, best_move) = negamax(&pos, 6, 1, Score::ZERO, Score::ZERO + 1, &mut ctx);
+            assert!(score > Score::ZERO);
+            assert_eq!(best_move.unwrap().to_uci(CastlingMode::Standard).to_string(), "d2d5");
+            results.push(ctx.nodes);
+        }
+        assert!(results[1] < results[0]);
+    }
+
+    #[test]
+    fn test_contempt_draw_score_perspective() {
+        // Black to move is stalemated
+        let pos = position("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        let tablebase = Tablebase;
+        let control = SearchControl::default();
+        let (alpha, beta) = (-Score::MAX / 2, Score::MAX / 2);
+        for (contempt, analyse_mode, root_turn, expected) in [
+            (0, false, Color::White, 0),
+            // The root side counts the draw as a loss, so its opponent counts it as a win
+            (30, false, Color::White, 30),
+            (30, false, Color::Black, -30),
+            (-30, false, Color::White, -30),
+            (30, true, Color::White, 0),
+        ] {
+            let options = SearchOptions { contempt, analyse_mode, ..Default::default() };
+            let mut observer = ();
+            let mut tt = TranspositionTable::new(1 << 16);
+            let mut ctx = SearchContext::new(&mut tt, &tablebase, &options, &control, &mut observer);
+            ctx.root_turn = root_turn;
+            assert_eq!(negamax(&pos, 3, 1, alpha, beta, &mut ctx).0, Score::Centipawn(expected));
+            assert_eq!(quiescence_search(&pos, 0, 1, alpha, beta, &mut ctx), Score::Centipawn(expected));
+        }
+    }
+
+    #[test]
+    fn test_skill_level_limits_search() {
+        let pos = position("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4");
+        let mut pick = |seed| {
+            let options = SearchOptions { skill_level: 0, seed: Some(seed), ..Default::default() };
+            let mut max_depth = 0;
+            let mut observer = |info: &SearchInfo| {
+                if let SearchInfo::Iteration { depth, .. } = info {
+                    max_depth = max_depth.max(*depth);
+                }
+            };
+            let result = search_with(&pos, &SearchLimits { depth: Some(10), ..Default::default() }, &options, &mut observer);
+            assert_eq!(max_depth, 1);
+            result.best_move.unwrap()
+        };
+        let moves: Vec<Move> = (0..20).map(&mut pick).collect();
+        assert!(moves.iter().all(|m| pos.is_legal(m)));
+        // The same seed picks the same move, and different seeds pick among the best lines
+        assert_eq!(pick(7), moves[7]);
+        assert!(moves.iter().collect::<HashSet<_>>().len() > 1);
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let pos = position("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4");
+        let mut nodes = 0;
+        let mut observer = |info: &SearchInfo| {
+            if let SearchInfo::Iteration { nodes: n, .. } = info {
+                nodes = *n;
+            }
+        };
+        let result = search(&pos, &SearchLimits { nodes: Some(5000), ..Default::default() }, &mut observer);
+        assert!(pos.is_legal(&result.best_move.unwrap()));
+        // The last complete iteration ends within the limit
+        assert!(nodes > 0 && nodes <= 5000);
+    }
+
+    #[test]
+    fn test_debug_reports_pawn_hash() {
+        let pos = position("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4");
+        let limits = SearchLimits { depth: Some(3), ..Default::default() };
+        for debug in [false, true] {
+            let mut messages = Vec::new();
+            let mut observer = |info: &SearchInfo| {
+                if let SearchInfo::Message(message) = info {
+                    messages.push(message.clone());
+                }
+            };
+            search_with(&pos, &limits, &SearchOptions { debug, ..Default::default() }, &mut observer);
+            assert_eq!(messages.iter().any(|message| message.starts_with("pawn hash hit rate")), debug);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use shakmaty::{Bitboard, Board, Color, Move, Role};
+
+use crate::evaluation::PIECE_VALUES;
+
+fn value(role: Role) -> i32 {
+    PIECE_VALUES[role as usize] as i32
+}
+
+// Static exchange evaluation: whether the exchange started by the move on its target
+// square wins at least the threshold for the side making it, with both sides always
+// recapturing with their least valuable piece and free to stop
+pub fn see_ge(board: &Board, mov: &Move, threshold: i32) -> bool {
+    let (from, to, captured) = match *mov {
+        Move::Normal { from, to, capture, .. } => (from, to, capture.map_or(0, value)),
+        Move::EnPassant { from, to } => (from, to, value(Role::Pawn)),
+        // Castling and drops do not capture, and nothing can be lost before they happen
+        Move::Castle { .. } | Move::Put { .. } => return threshold <= 0,
+    };
+    let Some(color) = board.color_at(from) else {
+        return threshold <= 0;
+    };
+
+    let mut swap = captured - threshold;
+    if swap < 0 {
+        return false;
+    }
+    swap = value(mov.promotion().unwrap_or(mov.role())) - swap;
+    if swap <= 0 {
+        return true;
+    }
+
+    let mut occupied = board.occupied() ^ Bitboard::from(from) ^ Bitboard::from(to);
+    if let Move::EnPassant { .. } = mov {
+        occupied ^= Bitboard::from(shakmaty::Square::from_coords(to.file(), from.rank()));
+    }
+    let mut side = color;
+    let mut result = true;
+    loop {
+        side = !side;
+        // Sliders behind the pieces that already captured join in through the occupancy
+        let attackers = (board.attacks_to(to, Color::White, occupied) | board.attacks_to(to, Color::Black, occupied)) & occupied;
+        let side_attackers = attackers & board.by_color(side);
+        if side_attackers.is_empty() {
+            break;
+        }
+        result = !result;
+
+        let Some(role) = Role::ALL.into_iter().find(|&role| (side_attackers & board.by_role(role)).any()) else {
+            break;
+        };
+        if role == Role::King {
+            // The king can only take last
+            return if (attackers & board.by_color(!side)).any() { !result } else { result };
+        }
+        swap = value(role) - swap;
+        if swap < result as i32 {
+            break;
+        }
+        occupied ^= Bitboard::from((side_attackers & board.by_role(role)).first().unwrap());
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::position;
+    use shakmaty::{uci::UciMove, Position};
+
+    fn see(fen: &str, uci: &str, threshold: i32) -> bool {
+        let pos = position(fen);
+        let mov = uci.parse::<UciMove>().unwrap().to_move(&pos).unwrap();
+        see_ge(pos.board(), &mov, threshold)
+    }
+
+    #[test]
+    fn test_see() {
+        // Undefended pawn
+        assert!(see("4k3/8/8/3p4/8/8/8/3RK3 w - - 0 1", "d1d5", 100));
+        assert!(!see("4k3/8/8/3p4/8/8/8/3RK3 w - - 0 1", "d1d5", 101));
+        // Pawn defended by a pawn: the rook is lost for it
+        assert!(!see("4k3/8/4p3/3p4/8/8/8/3RK3 w - - 0 1", "d1d5", 0));
+        assert!(see("4k3/8/4p3/3p4/8/8/8/3RK3 w - - 0 1", "d1d5", 100 - 500));
+        // Knight takes a pawn whose defending rook cannot recapture, as the white rook covers the square
+        assert!(see("3rk3/8/8/3p4/8/4N3/8/3RK3 w - - 0 1", "e3d5", 0));
+        // Pawn takes knight is always good
+        assert!(see("4k3/8/8/3n4/4P3/8/8/4K3 w - - 0 1", "e4d5", 325));
+        // Quiet move to an attacked square
+        assert!(!see("4k3/8/8/3p4/8/8/2Q5/4K3 w - - 0 1", "c2c4", 0));
+        assert!(see("4k3/8/8/8/8/8/2Q5/4K3 w - - 0 1", "c2c4", 0));
+    }
+}
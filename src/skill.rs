@@ -0,0 +1,147 @@
+use shakmaty::Move;
+
+use crate::score::Score;
+use crate::search::SearchOptions;
+use crate::utils::Prng;
+
+// Skill levels run from 0 to 20, where 20 is full strength and nothing is limited.
+// The levels have not been rated against opponents of known strength yet, so there is
+// no UCI_Elo to go with them.
+pub const MAX_LEVEL: u8 = 20;
+
+// Alternatives considered at the root when picking a weaker move
+const MULTI_PV: usize = 4;
+
+// Weaker play from three sources:
+// - shallower search, capped both by depth and by nodes, which cause most of the loss
+//   in strength, from about depth 10 and 200k nodes at level 19 down to depth 1 at level 0
+// - a random choice among the best few root moves, favoring worse ones more at low
+//   levels, which makes the weaker levels miss tactics the way people do
+// - noise on the evaluation below level 10, so that the weakest levels also misjudge
+//   quiet positions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Skill {
+    level: f64,
+}
+
+impl Skill {
+    // None at full strength
+    pub fn from_options(options: &SearchOptions) -> Option<Self> {
+        let level = options.skill_level.min(MAX_LEVEL) as f64;
+        (level < MAX_LEVEL as f64).then_some(Self { level })
+    }
+
+    pub fn depth(&self) -> i16 {
+        1 + (self.level / 2.0) as i16
+    }
+
+    // About 50% more nodes per level
+    pub fn nodes(&self) -> u64 {
+        (100.0 * 1.5f64.powf(self.level)) as u64
+    }
+
+    pub fn multi_pv(&self) -> usize {
+        MULTI_PV
+    }
+
+    // Largest evaluation noise in centipawns
+    pub fn noise_amplitude(&self) -> i16 {
+        ((10.0 - self.level).max(0.0) * 10.0) as i16
+    }
+
+    // Picks among the root moves, best first, by adding a random push to each score.
+    // The push is larger for lower levels and for worse moves, while the spread among
+    // the candidates, at most a pawn, bounds how far the random part reaches.
+    pub fn pick_move(&self, lines: &[(Move, Score)], rng: &mut Prng) -> Move {
+        let (top_move, top) = &lines[0];
+        // Mates are played or avoided as they are
+        let Score::Centipawn(top) = *top else {
+            return top_move.clone();
+        };
+        let candidates: Vec<(&Move, i32)> = lines
+            .iter()
+            .filter_map(|(mov, score)| match score {
+                Score::Centipawn(cp) => Some((mov, *cp as i32)),
+                Score::Mate(_) => None,
+            })
+            .collect();
+        let top = top as i32;
+        let last = candidates.last().map_or(top, |&(_, score)| score);
+        let delta = (top - last).min(100);
+        let weakness = (120.0 - 2.0 * self.level) as i32;
+
+        let mut best = (top_move, i32::MIN);
+        for (mov, score) in candidates {
+            let push = (weakness * (top - score) + delta * rng.below(weakness as u64) as i32) / 128;
+            if score + push >= best.1 {
+                best = (mov, score + push);
+            }
+        }
+        best.0.clone()
+    }
+}
+
+// Noise for a position, the same on every visit during one search so that the
+// transposition table stays consistent
+pub fn eval_noise(hash: u64, seed: u64, amplitude: i16) -> i16 {
+    if amplitude == 0 {
+        return 0;
+    }
+    let mixed = (hash ^ seed).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32;
+    (mixed % (2 * amplitude as u64 + 1)) as i16 - amplitude
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::{Role, Square};
+
+    fn quiet(from: Square, to: Square) -> Move {
+        Move::Normal { role: Role::Knight, from, capture: None, to, promotion: None }
+    }
+
+    #[test]
+    fn test_levels_from_options() {
+        let options = |skill_level| SearchOptions { skill_level, ..Default::default() };
+        assert_eq!(Skill::from_options(&SearchOptions::default()), None);
+        assert_eq!(Skill::from_options(&options(MAX_LEVEL + 5)), None);
+
+        let weakest = Skill::from_options(&options(0)).unwrap();
+        assert_eq!((weakest.depth(), weakest.noise_amplitude()), (1, 100));
+        let strong = Skill::from_options(&options(18)).unwrap();
+        assert!(strong.depth() > weakest.depth() && strong.nodes() > weakest.nodes());
+        assert_eq!(strong.noise_amplitude(), 0);
+    }
+
+    #[test]
+    fn test_pick_move() {
+        let lines = [
+            (quiet(Square::G1, Square::F3), Score::Centipawn(50)),
+            (quiet(Square::B1, Square::C3), Score::Centipawn(40)),
+            (quiet(Square::G1, Square::H3), Score::Centipawn(-300)),
+        ];
+        let mut rng = Prng::new(3);
+        let mut picked = Vec::new();
+        for level in [0.0, 19.0] {
+            let skill = Skill { level };
+            let moves: Vec<Move> = (0..200).map(|_| skill.pick_move(&lines, &mut rng)).collect();
+            picked.push(moves.iter().filter(|&m| *m != lines[0].0).count());
+        }
+        // Only the lowest levels can push a move three pawns worse to the top
+        assert!(!(0..200).any(|_| Skill { level: 19.0 }.pick_move(&lines, &mut rng) == lines[2].0));
+        // The weakest level strays from the best move more often, but both sometimes do
+        assert!(picked[0] > picked[1] && picked[1] > 0);
+
+        let mate = [(lines[1].0.clone(), Score::Mate(3)), (lines[0].0.clone(), Score::Centipawn(900))];
+        assert_eq!(Skill { level: 0.0 }.pick_move(&mate, &mut rng), mate[0].0);
+    }
+
+    #[test]
+    fn test_eval_noise() {
+        assert_eq!(eval_noise(12345, 1, 0), 0);
+        let noise: Vec<i16> = (0..1000).map(|hash| eval_noise(hash, 99, 50)).collect();
+        assert!(noise.iter().all(|n| (-50..=50).contains(n)));
+        assert!(noise.iter().any(|&n| n < -25) && noise.iter().any(|&n| n > 25));
+        assert_eq!(eval_noise(7, 99, 50), noise[7]);
+    }
+}
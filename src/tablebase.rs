@@ -0,0 +1,117 @@
+use shakmaty::{Chess, Move, Outcome, Position};
+
+use crate::kpk;
+
+// Positions with this many pieces or fewer are covered by the built-in tables
+const BUILTIN_MAX_PIECES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss,
+    Draw,
+    Win,
+}
+
+impl std::ops::Neg for Wdl {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Wdl::Loss => Wdl::Win,
+            Wdl::Draw => Wdl::Draw,
+            Wdl::Win => Wdl::Loss,
+        }
+    }
+}
+
+// Win/draw/loss results for the side to move, looked up in the built-in KPK bitbase.
+// There are no distance-to-zero tables and Syzygy files are not supported.
+#[derive(Clone, Default)]
+pub struct Tablebase;
+
+impl Tablebase {
+    pub fn max_pieces(&self) -> usize {
+        BUILTIN_MAX_PIECES
+    }
+
+    // Exact result for the side to move, if the position is in a table. Finished games
+    // are left to the search.
+    pub fn probe_wdl(&self, pos: &Chess) -> Option<Wdl> {
+        let board = pos.board();
+        if board.occupied().count() != 3 || pos.is_game_over() {
+            return None;
+        }
+        let pawn = board.pawns().single_square()?;
+        let strong = board.color_at(pawn).unwrap();
+        let strong_wins = kpk::probe(
+            strong,
+            board.king_of(strong).unwrap(),
+            pawn,
+            board.king_of(!strong).unwrap(),
+            pos.turn(),
+        );
+        Some(match (strong_wins, pos.turn() == strong) {
+            (false, _) => Wdl::Draw,
+            (true, true) => Wdl::Win,
+            (true, false) => Wdl::Loss,
+        })
+    }
+
+    // Root moves that keep the best result, if every move leads to a table position or
+    // ends the game. Without distance-to-zero tables the search has to find the way to
+    // convert among them.
+    pub fn probe_root(&self, pos: &Chess) -> Option<(Wdl, Vec<Move>)> {
+        let mut results = Vec::new();
+        for mov in pos.legal_moves() {
+            let mut new_pos = pos.clone();
+            new_pos.play_unchecked(&mov);
+            let wdl = match new_pos.outcome() {
+                Some(Outcome::Draw) => Wdl::Draw,
+                // Only the side that just moved can have won
+                Some(Outcome::Decisive { .. }) => Wdl::Win,
+                None => -self.probe_wdl(&new_pos)?,
+            };
+            results.push((wdl, mov));
+        }
+        let best = results.iter().map(|(wdl, _)| *wdl).max()?;
+
+        let moves = results
+            .into_iter()
+            .filter(|(wdl, _)| *wdl == best)
+            .map(|(_, mov)| mov)
+            .collect();
+        Some((best, moves))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::position;
+
+    #[test]
+    fn test_probe_wdl() {
+        let tb = Tablebase;
+        assert_eq!(tb.probe_wdl(&position("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1")), Some(Wdl::Win));
+        assert_eq!(tb.probe_wdl(&position("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1")), Some(Wdl::Loss));
+        assert_eq!(tb.probe_wdl(&position("8/3k4/8/3K4/3P4/8/8/8 w - - 0 1")), Some(Wdl::Draw));
+        // Not covered
+        assert_eq!(tb.probe_wdl(&position("4k3/8/8/8/8/8/8/3NK3 w - - 0 1")), None);
+        assert_eq!(tb.probe_wdl(&position("4k3/8/8/8/8/8/8/3QK3 w - - 0 1")), None);
+        assert_eq!(tb.probe_wdl(&position("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1")), None);
+    }
+
+    #[test]
+    fn test_probe_root_keeps_win() {
+        let tb = Tablebase;
+        let pos = position("4k3/8/8/4K3/4P3/8/8/8 w - - 0 1");
+        let (wdl, moves) = tb.probe_root(&pos).unwrap();
+        assert_eq!(wdl, Wdl::Win);
+        assert!(moves.len() < pos.legal_moves().len());
+        for mov in moves {
+            let mut new_pos = pos.clone();
+            new_pos.play_unchecked(&mov);
+            assert_eq!(tb.probe_wdl(&new_pos), Some(Wdl::Loss));
+        }
+    }
+}
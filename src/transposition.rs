@@ -1,4 +1,8 @@
-use shakmaty::Move;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use shakmaty::{Move, Role, Square};
 use crate::score::Score;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -15,26 +19,326 @@ pub struct TranspositionEntry {
     pub score: Score,
     pub node_type: NodeType,
     pub best_move: Option<Move>,
+    // The search that stored the entry
+    pub generation: u8,
 }
 
+#[derive(Clone)]
 pub struct TranspositionTable {
     entries: Vec<Option<TranspositionEntry>>,
     size: usize,
+    generation: u8,
 }
 
+// Hash files: a header of the magic bytes, the format version, the engine version, the
+// table size, the generation and the entry count, then the entries in use. Integers are
+// little-endian.
+const MAGIC: &[u8; 8] = b"VOINHASH";
+const FORMAT_VERSION: u16 = 1;
+const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+// Hash, depth, score kind and value, node type, generation, then the move as
+// kind, role, from, to, capture and promotion
+const ENTRY_SIZE: usize = 21;
+
+// Each search since an entry was stored makes it count as this many plies shallower
+// when deciding what to replace
+const AGE_DEPTH: i16 = 2;
+
 impl TranspositionTable {
     pub fn new(size: usize) -> Self {
         Self {
             entries: vec![None; size],
             size,
+            generation: 0,
         }
     }
     pub fn get(&self, hash: u64) -> Option<&TranspositionEntry> {
         let index = hash as usize % self.size;
         self.entries[index].as_ref().filter(|entry| entry.hash == hash)
     }
+    // A newer result for the same position always replaces the old one. Deeper entries of
+    // other positions are kept, so that a loaded table or the work of earlier searches is
+    // not lost to shallow results right away.
     pub fn insert(&mut self, hash: u64, depth: i16, score: Score, node_type: NodeType, best_move: Option<Move>) {
         let index = hash as usize % self.size;
-        self.entries[index] = Some(TranspositionEntry { hash, depth, score, node_type, best_move });
+        if let Some(entry) = &self.entries[index]
+            && entry.hash != hash
+        {
+            let age = self.generation.wrapping_sub(entry.generation) as i16;
+            if depth < entry.depth - AGE_DEPTH * age {
+                return;
+            }
+        }
+        self.entries[index] = Some(TranspositionEntry { hash, depth, score, node_type, best_move, generation: self.generation });
+    }
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+        self.generation = 0;
+    }
+    // Entries kept from earlier searches count as older from now on
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+    // Permille of entries stored by the current search, sampled from the start of the table
+    pub fn hashfull(&self) -> u16 {
+        let sample = &self.entries[..self.size.min(1000)];
+        let used = sample.iter().flatten().filter(|entry| entry.generation == self.generation).count();
+        (used * 1000 / sample.len()) as u16
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let used: Vec<&TranspositionEntry> = self.entries.iter().flatten().collect();
+        let mut bytes = Vec::with_capacity(40 + used.len() * ENTRY_SIZE);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.push(ENGINE_VERSION.len() as u8);
+        bytes.extend_from_slice(ENGINE_VERSION.as_bytes());
+        bytes.extend_from_slice(&(self.size as u64).to_le_bytes());
+        bytes.push(self.generation);
+        bytes.extend_from_slice(&(used.len() as u64).to_le_bytes());
+        for entry in used {
+            bytes.extend_from_slice(&entry.hash.to_le_bytes());
+            bytes.extend_from_slice(&entry.depth.to_le_bytes());
+            let (kind, value) = match entry.score {
+                Score::Centipawn(cp) => (0, cp),
+                Score::Mate(n) => (1, n as i16),
+            };
+            bytes.push(kind);
+            bytes.extend_from_slice(&value.to_le_bytes());
+            bytes.push(entry.node_type as u8);
+            bytes.push(entry.generation);
+            bytes.extend_from_slice(&encode_move(entry.best_move.as_ref()));
+        }
+        bytes
+    }
+
+    // Replaces the contents with a saved table of the same size. The table is left
+    // untouched when the file does not validate.
+    pub fn load_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a hash file"));
+        }
+        let format = u16::from_le_bytes(reader.array()?);
+        if format != FORMAT_VERSION {
+            return Err(invalid(&format!("unsupported hash file format {}", format)));
+        }
+        let version_len = reader.byte()? as usize;
+        let version = reader.take(version_len)?;
+        if version != ENGINE_VERSION.as_bytes() {
+            return Err(invalid(&format!(
+                "hash file from engine version {}, this is {}",
+                String::from_utf8_lossy(version),
+                ENGINE_VERSION
+            )));
+        }
+        let size = u64::from_le_bytes(reader.array()?);
+        if size != self.size as u64 {
+            return Err(invalid(&format!("hash file has {} entries, the table has {}", size, self.size)));
+        }
+        let generation = reader.byte()?;
+        let count = u64::from_le_bytes(reader.array()?);
+        if count > size || reader.bytes.len() as u64 != count * ENTRY_SIZE as u64 {
+            return Err(invalid("hash file entry count does not match its length"));
+        }
+
+        let mut entries = vec![None; self.size];
+        for _ in 0..count {
+            let hash = u64::from_le_bytes(reader.array()?);
+            let depth = i16::from_le_bytes(reader.array()?);
+            let kind = reader.byte()?;
+            let value = i16::from_le_bytes(reader.array()?);
+            let score = match kind {
+                0 => Score::Centipawn(value),
+                1 => Score::Mate(i8::try_from(value).map_err(|_| invalid("mate distance out of range"))?),
+                _ => return Err(invalid("invalid score in hash file")),
+            };
+            let node_type = match reader.byte()? {
+                0 => NodeType::Exact,
+                1 => NodeType::UpperBound,
+                2 => NodeType::LowerBound,
+                _ => return Err(invalid("invalid node type in hash file")),
+            };
+            let entry_generation = reader.byte()?;
+            let best_move = decode_move(reader.array()?).ok_or_else(|| invalid("invalid move in hash file"))?;
+            entries[hash as usize % self.size] =
+                Some(TranspositionEntry { hash, depth, score, node_type, best_move, generation: entry_generation });
+        }
+        self.entries = entries;
+        self.generation = generation;
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn load(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.load_bytes(&fs::read(path)?)
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated hash file"));
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+}
+
+// Kind, role, from, to, capture and promotion, with 0 for no piece
+fn encode_move(mov: Option<&Move>) -> [u8; 6] {
+    let role = |role: Option<Role>| role.map_or(0, |role| role as u8);
+    match mov {
+        None => [0; 6],
+        Some(&Move::Normal { role: moved, from, capture, to, promotion }) => {
+            [1, moved as u8, from as u8, to as u8, role(capture), role(promotion)]
+        }
+        Some(&Move::EnPassant { from, to }) => [2, Role::Pawn as u8, from as u8, to as u8, Role::Pawn as u8, 0],
+        Some(&Move::Castle { king, rook }) => [3, Role::King as u8, king as u8, rook as u8, 0, 0],
+        Some(&Move::Put { role: put, to }) => [4, put as u8, 0, to as u8, 0, 0],
+    }
+}
+
+// None when the bytes do not describe a move, Some(None) for no move
+fn decode_move(bytes: [u8; 6]) -> Option<Option<Move>> {
+    let [kind, role, from, to, capture, promotion] = bytes;
+    let optional_role = |value: u8| if value == 0 { Some(None) } else { Role::try_from(value).ok().map(Some) };
+    let mov = match kind {
+        0 => return Some(None),
+        1 => Move::Normal {
+            role: Role::try_from(role).ok()?,
+            from: Square::try_from(from).ok()?,
+            capture: optional_role(capture)?,
+            to: Square::try_from(to).ok()?,
+            promotion: optional_role(promotion)?,
+        },
+        2 => Move::EnPassant { from: Square::try_from(from).ok()?, to: Square::try_from(to).ok()? },
+        3 => Move::Castle { king: Square::try_from(from).ok()?, rook: Square::try_from(to).ok()? },
+        4 => Move::Put { role: Role::try_from(role).ok()?, to: Square::try_from(to).ok()? },
+        _ => return None,
+    };
+    Some(Some(mov))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled_table() -> TranspositionTable {
+        let mut table = TranspositionTable::new(1024);
+        table.new_search();
+        let moves = [
+            Move::Normal { role: Role::Knight, from: Square::G1, capture: None, to: Square::F3, promotion: None },
+            Move::Normal { role: Role::Pawn, from: Square::B7, capture: Some(Role::Rook), to: Square::A8, promotion: Some(Role::Queen) },
+            Move::EnPassant { from: Square::E5, to: Square::D6 },
+            Move::Castle { king: Square::E1, rook: Square::H1 },
+            Move::Put { role: Role::Knight, to: Square::F7 },
+        ];
+        for (i, mov) in moves.into_iter().enumerate() {
+            table.insert(i as u64 * 7919, i as i16, Score::Centipawn(-(i as i16) * 30), NodeType::LowerBound, Some(mov));
+        }
+        table.insert(5000, 9, Score::Mate(-4), NodeType::Exact, None);
+        table
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let table = filled_table();
+        let mut loaded = TranspositionTable::new(1024);
+        loaded.load_bytes(&table.to_bytes()).unwrap();
+        assert_eq!(loaded.generation, 1);
+        for hash in [0, 7919, 2 * 7919, 3 * 7919, 4 * 7919, 5000] {
+            let (expected, entry) = (table.get(hash).unwrap(), loaded.get(hash).unwrap());
+            assert_eq!((entry.depth, entry.score, entry.node_type), (expected.depth, expected.score, expected.node_type));
+            assert_eq!((&entry.best_move, entry.generation), (&expected.best_move, 1));
+        }
+        assert!(loaded.get(1).is_none());
+        assert_eq!(loaded.hashfull(), table.hashfull());
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_files() {
+        let bytes = filled_table().to_bytes();
+        let mut table = TranspositionTable::new(1024);
+        table.insert(42, 1, Score::ZERO, NodeType::Exact, None);
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        let mut wrong_format = bytes.clone();
+        wrong_format[8] = 2;
+        let mut wrong_version = bytes.clone();
+        wrong_version[11] = b'9';
+        let mut bad_move = bytes.clone();
+        let last = bad_move.len() - 3;
+        bad_move[last] = 64;
+        for invalid in [&wrong_magic[..], &wrong_format[..], &wrong_version[..], &bytes[..bytes.len() - 1], &bad_move[..], &[]] {
+            assert!(table.load_bytes(invalid).is_err());
+        }
+        // A table of another size
+        assert!(TranspositionTable::new(2048).load_bytes(&bytes).is_err());
+        // Failed loads keep the old contents
+        assert!(table.get(42).is_some());
+    }
+
+    #[test]
+    fn test_generations() {
+        let mut table = TranspositionTable::new(1000);
+        table.insert(1, 1, Score::ZERO, NodeType::Exact, None);
+        assert_eq!(table.hashfull(), 1);
+        // Old entries stay usable but no longer count as full
+        table.new_search();
+        assert!(table.get(1).is_some());
+        assert_eq!(table.hashfull(), 0);
+        table.clear();
+        assert!(table.get(1).is_none());
+    }
+
+    #[test]
+    fn test_replacement() {
+        let mut table = TranspositionTable::new(1000);
+        table.insert(1, 6, Score::Centipawn(10), NodeType::Exact, None);
+        // Shallower results of other positions keep a deeper entry
+        table.insert(1001, 3, Score::ZERO, NodeType::Exact, None);
+        assert_eq!(table.get(1).unwrap().score, Score::Centipawn(10));
+        // A newer result for the same position replaces it, even when shallower
+        table.insert(1, 5, Score::Centipawn(20), NodeType::UpperBound, None);
+        let entry = table.get(1).unwrap();
+        assert_eq!((entry.depth, entry.score, entry.node_type), (5, Score::Centipawn(20), NodeType::UpperBound));
+        table.insert(1, 6, Score::Centipawn(30), NodeType::LowerBound, None);
+        assert_eq!(table.get(1).unwrap().score, Score::Centipawn(30));
+
+        // A loaded table survives the next search, then gives way as it ages
+        let mut loaded = TranspositionTable::new(1000);
+        loaded.load_bytes(&table.to_bytes()).unwrap();
+        loaded.new_search();
+        loaded.insert(1001, 3, Score::ZERO, NodeType::Exact, None);
+        assert!(loaded.get(1).is_some());
+        loaded.insert(1001, 4, Score::ZERO, NodeType::Exact, None);
+        assert!(loaded.get(1).is_none() && loaded.get(1001).is_some());
+
+        loaded.insert(2, 10, Score::ZERO, NodeType::Exact, None);
+        for _ in 0..4 {
+            loaded.new_search();
+        }
+        loaded.insert(1002, 2, Score::ZERO, NodeType::Exact, None);
+        assert!(loaded.get(2).is_none());
     }
 }
@@ -18,13 +18,37 @@ pub fn signum(number: isize) -> i8 {
     -1
 }
 
-pub fn clamp<T: PartialOrd>(low: T, value: T, high: T) -> T {
-    debug_assert!(low < high, "low is bigger than high!");
-    if value < low {
-        low
-    } else if value > high {
-        high
-    } else {
-        value
+// Xorshift64* generator, good enough for picking book moves
+pub struct Prng(u64);
+
+impl Prng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub fn from_time() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
     }
-}
\ No newline at end of file
+
+    // Uniform enough in 0..n for small n
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
+
+// Standard chess position from a FEN, for tests
+#[cfg(test)]
+pub fn position(fen: &str) -> shakmaty::Chess {
+    use shakmaty::{fen::Fen, CastlingMode};
+    fen.parse::<Fen>().unwrap().into_position(CastlingMode::Standard).unwrap()
+}
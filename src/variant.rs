@@ -0,0 +1,51 @@
+use shakmaty::variant::{
+    Antichess, Atomic, Crazyhouse, Horde, KingOfTheHill, RacingKings, ThreeCheck, Variant, VariantPosition,
+};
+use shakmaty::{Chess, Position};
+
+// Positions the engine can search. Knowledge that only holds in standard chess,
+// such as endgame tables and opening books, goes through as_chess.
+pub trait EnginePosition: Position + Clone {
+    fn variant(&self) -> Variant;
+
+    fn as_chess(&self) -> Option<&Chess> {
+        None
+    }
+}
+
+impl EnginePosition for Chess {
+    fn variant(&self) -> Variant {
+        Variant::Chess
+    }
+
+    fn as_chess(&self) -> Option<&Chess> {
+        Some(self)
+    }
+}
+
+impl EnginePosition for VariantPosition {
+    fn variant(&self) -> Variant {
+        VariantPosition::variant(self)
+    }
+
+    fn as_chess(&self) -> Option<&Chess> {
+        match self {
+            VariantPosition::Chess(pos) => Some(pos),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_engine_position {
+    ($($position:ident),*) => {
+        $(
+            impl EnginePosition for $position {
+                fn variant(&self) -> Variant {
+                    Variant::$position
+                }
+            }
+        )*
+    };
+}
+
+impl_engine_position!(Atomic, Antichess, KingOfTheHill, ThreeCheck, Crazyhouse, RacingKings, Horde);
@@ -0,0 +1,551 @@
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::Duration;
+
+use shakmaty::fen::Fen;
+use shakmaty::uci::UciMove;
+use shakmaty::variant::{Variant, VariantPosition};
+use shakmaty::{CastlingMode, Color, Move, Outcome, Position};
+
+use crate::engine::Engine;
+use crate::protocol::{BackgroundSearch, Event};
+use crate::search::{Bound, SearchInfo, SearchLimits};
+
+// What the running search is for
+enum Job {
+    Think,
+    // Searching the position after the expected reply
+    Ponder(Move),
+    Analyze,
+}
+
+// Chess Engine Communication Protocol (xboard/winboard) front end
+struct Xboard {
+    variant: Variant,
+    pos: VariantPosition,
+    history: Vec<VariantPosition>,
+    // None in force mode
+    engine_color: Option<Color>,
+    post: bool,
+    ponder: bool,
+    analyze: bool,
+    depth: Option<i16>,
+    move_time: Option<Duration>,
+    moves_per_session: u32,
+    increment: Duration,
+    time_left: Duration,
+    engine: Engine,
+    search: Option<(BackgroundSearch, Job)>,
+    next_id: u64,
+    tx: Sender<Event>,
+}
+
+fn variant_from_xboard(name: &str) -> Option<Variant> {
+    match name {
+        "normal" => Some(Variant::Chess),
+        "giveaway" => Some(Variant::Antichess),
+        _ => Variant::from_uci(name).ok(),
+    }
+}
+
+fn variant_to_xboard(variant: Variant) -> &'static str {
+    match variant {
+        Variant::Chess => "normal",
+        Variant::Antichess => "giveaway",
+        _ => variant.uci(),
+    }
+}
+
+fn print_features() {
+    let variants: Vec<&str> = Variant::ALL.into_iter().map(variant_to_xboard).collect();
+    println!(
+        "feature myname=\"Voin\" setboard=1 usermove=1 ping=1 analyze=1 colors=0 sigint=0 sigterm=0 reuse=1 variants=\"{}\"",
+        variants.join(",")
+    );
+    println!("feature done=1");
+}
+
+// Thinking output: depth, score, time in centiseconds, nodes and the principal variation
+fn print_thinking(info: &SearchInfo) {
+    if let SearchInfo::Iteration { depth, score, bound: Bound::Exact, nodes, time, pv, .. } = info {
+        let pv: Vec<String> = pv.iter().map(|m| m.to_uci(CastlingMode::Standard).to_string()).collect();
+        println!("{} {} {} {} {}", depth, score.to_xboard(), time.as_millis() / 10, nodes, pv.join(" "));
+    }
+}
+
+// "40 5 0" or "0 2:30 1": moves per session, base time in minutes[:seconds], increment in seconds
+fn parse_level(args: &[&str]) -> Option<(u32, Duration, Duration)> {
+    let moves = args.first()?.parse().ok()?;
+    let base = match args.get(1)?.split_once(':') {
+        Some((minutes, seconds)) => minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?,
+        None => args.get(1)?.parse::<u64>().ok()? * 60,
+    };
+    let increment = args.get(2)?.parse::<f64>().ok()?;
+    Some((moves, Duration::from_secs(base), Duration::from_secs_f64(increment)))
+}
+
+impl Xboard {
+    fn new(engine: Engine, tx: Sender<Event>) -> Self {
+        Self {
+            variant: Variant::Chess,
+            pos: VariantPosition::new(Variant::Chess),
+            history: Vec::new(),
+            engine_color: Some(Color::Black),
+            post: false,
+            ponder: false,
+            analyze: false,
+            depth: None,
+            move_time: None,
+            moves_per_session: 0,
+            increment: Duration::ZERO,
+            time_left: Duration::from_secs(300),
+            engine,
+            search: None,
+            next_id: 0,
+            tx,
+        }
+    }
+
+    // Returns false on quit
+    fn handle_command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(&command) = tokens.first() else {
+            return true;
+        };
+        let args = &tokens[1..];
+        match command {
+            "protover" => print_features(),
+            "new" => {
+                self.cancel_search();
+                self.engine.clear_hash();
+                self.variant = Variant::Chess;
+                self.set_position(VariantPosition::new(self.variant));
+                self.engine_color = Some(Color::Black);
+                self.depth = None;
+                self.move_time = None;
+            }
+            "variant" => {
+                if let Some(variant) = args.first().and_then(|name| variant_from_xboard(name)) {
+                    self.cancel_search();
+                    self.variant = variant;
+                    self.set_position(VariantPosition::new(variant));
+                }
+            }
+            "setboard" => {
+                let position = args
+                    .join(" ")
+                    .parse::<Fen>()
+                    .ok()
+                    .and_then(|fen| VariantPosition::from_setup(self.variant, fen.into_setup(), CastlingMode::Standard).ok());
+                match position {
+                    Some(pos) => {
+                        self.cancel_search();
+                        self.set_position(pos);
+                    }
+                    None => println!("tellusererror Illegal position"),
+                }
+            }
+            "force" => {
+                self.cancel_search();
+                self.engine_color = None;
+            }
+            "go" => {
+                self.cancel_search();
+                self.engine_color = Some(self.pos.turn());
+                self.start_search(Job::Think);
+            }
+            "playother" => {
+                self.cancel_search();
+                self.engine_color = Some(!self.pos.turn());
+            }
+            "usermove" => {
+                if let Some(mov) = args.first() {
+                    self.user_move(mov);
+                }
+            }
+            "?" => {
+                if let Some((search, Job::Think)) = &self.search {
+                    search.stop();
+                }
+            }
+            "undo" | "remove" => {
+                self.cancel_search();
+                let plies = if command == "undo" { 1 } else { 2 };
+                for _ in 0..plies {
+                    if let Some(pos) = self.history.pop() {
+                        self.pos = pos;
+                    }
+                }
+                self.restart_analysis();
+            }
+            "level" => {
+                if let Some((moves, base, increment)) = parse_level(args) {
+                    self.moves_per_session = moves;
+                    self.time_left = base;
+                    self.increment = increment;
+                    self.move_time = None;
+                }
+            }
+            "st" => self.move_time = args.first().and_then(|s| s.parse().ok()).map(Duration::from_secs),
+            "sd" => self.depth = args.first().and_then(|s| s.parse().ok()),
+            "time" => {
+                if let Some(centiseconds) = args.first().and_then(|s| s.parse::<u64>().ok()) {
+                    self.time_left = Duration::from_millis(centiseconds * 10);
+                }
+            }
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "hard" => self.ponder = true,
+            "easy" => {
+                self.ponder = false;
+                if let Some((_, Job::Ponder(_))) = &self.search {
+                    self.cancel_search();
+                }
+            }
+            "ping" => println!("pong {}", args.first().unwrap_or(&"")),
+            "analyze" => {
+                self.cancel_search();
+                self.analyze = true;
+                self.engine_color = None;
+                self.restart_analysis();
+            }
+            "exit" => {
+                self.cancel_search();
+                self.analyze = false;
+            }
+            "result" => {
+                self.cancel_search();
+                self.engine_color = None;
+            }
+            "quit" => {
+                self.cancel_search();
+                return false;
+            }
+            // xboard, accepted, rejected, random, computer, name, rating, otim, ".", white, black
+            _ => {}
+        }
+        true
+    }
+
+    fn set_position(&mut self, pos: VariantPosition) {
+        self.pos = pos;
+        self.history.clear();
+        self.restart_analysis();
+    }
+
+    fn play(&mut self, mov: &Move) {
+        self.history.push(self.pos.clone());
+        self.pos.play_unchecked(mov);
+    }
+
+    fn user_move(&mut self, text: &str) {
+        let Some(mov) = text.parse::<UciMove>().ok().and_then(|uci| uci.to_move(&self.pos).ok()) else {
+            println!("Illegal move: {}", text);
+            return;
+        };
+
+        // The expected reply: keep the search and let the clock run
+        if let Some((search, Job::Ponder(expected))) = &self.search
+            && *expected == mov
+        {
+            search.ponderhit();
+            self.search = self.search.take().map(|(search, _)| (search, Job::Think));
+            self.play(&mov);
+            return;
+        }
+
+        self.cancel_search();
+        self.play(&mov);
+        if self.analyze {
+            self.restart_analysis();
+        } else if self.engine_color == Some(self.pos.turn()) && !self.report_game_over() {
+            self.start_search(Job::Think);
+        }
+    }
+
+    fn search_done(&mut self, id: u64, best_move: Option<Move>, pv: Vec<Move>) {
+        match self.search.take() {
+            Some((search, Job::Think)) if search.id == id => {
+                // Searches only start in positions with a move to play
+                let Some(best_move) = best_move else { return };
+                println!("move {}", best_move.to_uci(CastlingMode::Standard));
+                self.play(&best_move);
+                if self.report_game_over() {
+                    return;
+                }
+                // Think on the opponent's time about the reply we expect
+                if self.ponder
+                    && let Some(expected) = pv.get(1)
+                    && self.pos.is_legal(expected)
+                {
+                    let mut pos = self.pos.clone();
+                    pos.play_unchecked(expected);
+                    if !pos.is_game_over() {
+                        self.launch(pos, Job::Ponder(expected.clone()), true);
+                    }
+                }
+            }
+            // Results of searches that were given up
+            other => self.search = other,
+        }
+    }
+
+    fn report_game_over(&self) -> bool {
+        let Some(result) = game_result(&self.pos) else {
+            return false;
+        };
+        println!("{}", result);
+        true
+    }
+
+    fn time_budget(&self) -> Duration {
+        if let Some(move_time) = self.move_time {
+            return move_time;
+        }
+        let moves_to_go = if self.moves_per_session > 0 {
+            self.moves_per_session - (self.pos.fullmoves().get() - 1) % self.moves_per_session
+        } else {
+            40
+        };
+        (self.time_left / moves_to_go + self.increment).min(self.time_left / 2)
+    }
+
+    fn start_search(&mut self, job: Job) {
+        // The GUI waits for a move, so it gets the result instead
+        if matches!(job, Job::Think) && self.report_game_over() {
+            return;
+        }
+        if self.pos.is_game_over() {
+            return;
+        }
+        self.launch(self.pos.clone(), job, false);
+    }
+
+    fn launch(&mut self, pos: VariantPosition, job: Job, pondering: bool) {
+        let infinite = matches!(job, Job::Analyze);
+        let limits = SearchLimits {
+            time: if infinite { None } else { Some(self.time_budget()) },
+            depth: if infinite { None } else { self.depth },
+            infinite,
+            ponder: pondering,
+            nodes: None,
+        };
+        let post = self.post || infinite;
+        let observer = move |info: &SearchInfo| {
+            if post {
+                print_thinking(info);
+            }
+        };
+        self.engine.set_variant_position(pos);
+        self.next_id += 1;
+        let search = BackgroundSearch::start(self.next_id, &self.engine, limits, observer, self.tx.clone());
+        self.search = Some((search, job));
+    }
+
+    fn restart_analysis(&mut self) {
+        if self.analyze {
+            self.cancel_search();
+            self.start_search(Job::Analyze);
+        }
+    }
+
+    fn cancel_search(&mut self) {
+        if let Some((search, _)) = self.search.take() {
+            search.stop();
+        }
+    }
+}
+
+pub fn run(engine: Engine, events: Receiver<Event>, tx: Sender<Event>) {
+    let mut xboard = Xboard::new(engine, tx);
+    for event in events {
+        match event {
+            Event::Line(line) => {
+                if !xboard.handle_command(&line) {
+                    break;
+                }
+            }
+            Event::SearchDone(id, result) => xboard.search_done(id, result.best_move, result.pv),
+        }
+    }
+}
+
+// The result line for a finished game
+fn game_result(pos: &VariantPosition) -> Option<String> {
+    let result = match pos.outcome()? {
+        Outcome::Decisive { winner } => {
+            let score = if winner == Color::White { "1-0" } else { "0-1" };
+            let name = if winner == Color::White { "White" } else { "Black" };
+            // Variants also end with wins other than checkmate
+            let how = if pos.is_checkmate() { "mates" } else { "wins" };
+            format!("{} {{{} {}}}", score, name, how)
+        }
+        Outcome::Draw if pos.is_stalemate() => "1/2-1/2 {Stalemate}".to_string(),
+        Outcome::Draw if pos.is_insufficient_material() => "1/2-1/2 {Insufficient material}".to_string(),
+        Outcome::Draw => "1/2-1/2 {Draw}".to_string(),
+    };
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    use shakmaty::EnPassantMode;
+
+    // A shallow fixed depth keeps the searches short
+    fn xboard() -> (Xboard, Receiver<Event>) {
+        let (tx, rx) = mpsc::channel();
+        let mut xboard = Xboard::new(Engine::new(), tx);
+        xboard.handle_command("sd 2");
+        (xboard, rx)
+    }
+
+    // Hands finished searches to the front end until the engine has moved
+    fn wait_for_move(xboard: &mut Xboard, rx: &Receiver<Event>) {
+        while let Some((_, Job::Think)) = &xboard.search {
+            if let Event::SearchDone(id, result) = rx.recv().unwrap() {
+                xboard.search_done(id, result.best_move, result.pv);
+            }
+        }
+    }
+
+    fn fen(xboard: &Xboard) -> String {
+        Fen::from_position(xboard.pos.clone(), EnPassantMode::Legal).to_string()
+    }
+
+    fn search_id(xboard: &Xboard) -> Option<u64> {
+        xboard.search.as_ref().map(|(search, _)| search.id)
+    }
+
+    #[test]
+    fn test_parse_level() {
+        let (moves, base, increment) = parse_level(&["40", "5", "0"]).unwrap();
+        assert_eq!((moves, base, increment), (40, Duration::from_secs(300), Duration::ZERO));
+        let (moves, base, increment) = parse_level(&["0", "2:30", "1.5"]).unwrap();
+        assert_eq!((moves, base, increment), (0, Duration::from_secs(150), Duration::from_millis(1500)));
+        assert!(parse_level(&["40"]).is_none());
+    }
+
+    #[test]
+    fn test_variant_names() {
+        for variant in Variant::ALL {
+            assert_eq!(variant_from_xboard(variant_to_xboard(variant)), Some(variant));
+        }
+    }
+
+    #[test]
+    fn test_usermove_force_and_go() {
+        let (mut xboard, rx) = xboard();
+        // The engine plays black by default and answers at once
+        xboard.handle_command("usermove e2e4");
+        wait_for_move(&mut xboard, &rx);
+        assert_eq!((xboard.history.len(), xboard.pos.turn()), (2, Color::White));
+
+        // In force mode moves are only played
+        xboard.handle_command("force");
+        xboard.handle_command("usermove d2d4");
+        assert!(xboard.search.is_none());
+        assert_eq!((xboard.history.len(), xboard.engine_color), (3, None));
+
+        // go makes the engine play the side to move
+        xboard.handle_command("go");
+        assert_eq!(xboard.engine_color, Some(Color::Black));
+        wait_for_move(&mut xboard, &rx);
+        assert_eq!((xboard.history.len(), xboard.pos.turn()), (4, Color::White));
+
+        xboard.handle_command("usermove e2e5");
+        assert_eq!(xboard.history.len(), 4);
+    }
+
+    #[test]
+    fn test_undo_and_remove() {
+        let (mut xboard, _rx) = xboard();
+        xboard.handle_command("force");
+        let mut fens = vec![fen(&xboard)];
+        for mov in ["e2e4", "e7e5", "g1f3"] {
+            xboard.handle_command(&format!("usermove {}", mov));
+            fens.push(fen(&xboard));
+        }
+        xboard.handle_command("undo");
+        assert_eq!(fen(&xboard), fens[2]);
+        xboard.handle_command("remove");
+        assert_eq!(fen(&xboard), fens[0]);
+        assert!(xboard.history.is_empty() && xboard.search.is_none());
+        // Nothing left to take back
+        xboard.handle_command("undo");
+        assert_eq!(fen(&xboard), fens[0]);
+    }
+
+    #[test]
+    fn test_ponder_hit_and_miss() {
+        let (mut xboard, rx) = xboard();
+        xboard.handle_command("hard");
+        xboard.handle_command("usermove e2e4");
+        wait_for_move(&mut xboard, &rx);
+        let Some((_, Job::Ponder(expected))) = &xboard.search else {
+            panic!("not pondering after the engine's move");
+        };
+
+        // The expected reply turns the ponder search into the real one
+        let expected = expected.to_uci(CastlingMode::Standard).to_string();
+        let ponder_id = search_id(&xboard);
+        xboard.handle_command(&format!("usermove {}", expected));
+        assert!(matches!(xboard.search, Some((_, Job::Think))));
+        assert_eq!(search_id(&xboard), ponder_id);
+        wait_for_move(&mut xboard, &rx);
+        assert_eq!(xboard.history.len(), 4);
+
+        // Any other reply drops the ponder search and starts a new one
+        let Some((_, Job::Ponder(expected))) = &xboard.search else {
+            panic!("not pondering after the engine's move");
+        };
+        let other = xboard.pos.legal_moves().into_iter().find(|mov| mov != expected).unwrap();
+        let ponder_id = search_id(&xboard);
+        xboard.handle_command(&format!("usermove {}", other.to_uci(CastlingMode::Standard)));
+        assert!(matches!(xboard.search, Some((_, Job::Think))));
+        assert_ne!(search_id(&xboard), ponder_id);
+        wait_for_move(&mut xboard, &rx);
+        assert_eq!(xboard.history.len(), 6);
+    }
+
+    #[test]
+    fn test_analyze_and_exit() {
+        let (mut xboard, _rx) = xboard();
+        xboard.handle_command("analyze");
+        assert!(xboard.analyze && xboard.engine_color.is_none());
+        assert!(matches!(xboard.search, Some((_, Job::Analyze))));
+
+        // Moves restart the analysis on the new position instead of answering them
+        let first = search_id(&xboard);
+        xboard.handle_command("usermove e2e4");
+        assert!(matches!(xboard.search, Some((_, Job::Analyze))));
+        assert_ne!(search_id(&xboard), first);
+        assert_eq!(xboard.pos.turn(), Color::Black);
+        xboard.handle_command("undo");
+        assert!(matches!(xboard.search, Some((_, Job::Analyze))));
+        assert_eq!(xboard.pos.turn(), Color::White);
+
+        xboard.handle_command("exit");
+        assert!(!xboard.analyze && xboard.search.is_none());
+    }
+
+    #[test]
+    fn test_game_result() {
+        let result = |fen: &str| {
+            let setup = fen.parse::<Fen>().unwrap().into_setup();
+            game_result(&VariantPosition::from_setup(Variant::Chess, setup, CastlingMode::Standard).unwrap())
+        };
+        assert_eq!(result("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").as_deref(), Some("1-0 {White mates}"));
+        assert_eq!(result("6k1/8/8/8/8/8/5PPP/r5K1 w - - 0 1").as_deref(), Some("0-1 {Black mates}"));
+        assert_eq!(result("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").as_deref(), Some("1/2-1/2 {Stalemate}"));
+        assert_eq!(result("7k/8/6K1/8/8/8/8/8 b - - 0 1").as_deref(), Some("1/2-1/2 {Insufficient material}"));
+        assert_eq!(result("7k/8/6K1/8/8/8/8/7R b - - 0 1"), None);
+
+        // go in a finished game answers with the result instead of starting a search
+        let (mut xboard, _rx) = xboard();
+        xboard.handle_command("setboard R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1");
+        xboard.handle_command("go");
+        assert!(xboard.search.is_none());
+    }
+}